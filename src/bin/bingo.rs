@@ -0,0 +1,99 @@
+//! Interactive bingo on top of `day4`, the program is the caller.
+//!
+//! Usage: bingo [--puzzle FILE] [--players NAME,...] [--patterns PATTERN,...]
+//!              [--seed N] [--ansi] [--replay] [--random-draws]
+//!
+//! Without `--puzzle` every player gets a random 75-ball card. With it the
+//! players are named after the puzzle's boards in order, and
+//! `--random-draws` shuffles the puzzle's numbers. Patterns are `row`,
+//! `column`, `diagonal`, `corners`, `x` and `full`. `--replay` steps through
+//! the recorded turns of a puzzle instead of playing it.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rs_advent_of_code_2021::day4::{
+    parse_boards, step_through, BingoGame, Highlight, STANDARD_PATTERNS,
+};
+use std::{env, fs, io, process, time::SystemTime};
+
+fn run() -> Result<(), String> {
+    let mut puzzle = None;
+    let mut players: Option<Vec<String>> = None;
+    let mut patterns = STANDARD_PATTERNS.to_vec();
+    let mut seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_nanos() as u64);
+    let mut highlight = Highlight::Brackets;
+    let mut replay = false;
+    let mut random_draws = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("'{arg}' needs a value"));
+        match arg.as_str() {
+            "--puzzle" => puzzle = Some(value()?),
+            "--players" => players = Some(value()?.split(',').map(str::to_owned).collect()),
+            "--patterns" => {
+                patterns = value()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?
+            }
+            "--seed" => {
+                seed = value()?
+                    .parse()
+                    .map_err(|err| format!("invalid seed: {err}"))?
+            }
+            "--ansi" => highlight = Highlight::Ansi,
+            "--replay" => replay = true,
+            "--random-draws" => random_draws = true,
+            _ => return Err(format!("unknown argument '{arg}'")),
+        }
+    }
+
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut puzzle = match puzzle {
+        Some(path) => {
+            let input = fs::read_to_string(&path).map_err(|err| format!("{path}: {err}"))?;
+            Some(parse_boards(&input, false).map_err(|err| format!("{path}: {err}"))?)
+        }
+        None => None,
+    };
+
+    match puzzle.as_mut() {
+        Some(input) if random_draws => input.shuffle_numbers(&mut rng),
+        None if random_draws => return Err("--random-draws needs --puzzle".to_owned()),
+        _ => {}
+    }
+
+    if replay {
+        let input = puzzle.ok_or("--replay needs --puzzle")?;
+        if players.is_some() {
+            return Err("--players doesn't apply to --replay".to_owned());
+        }
+        let turns = input.replay_with(&patterns);
+        return step_through(&turns, io::stdin().lock(), io::stdout(), highlight)
+            .map_err(|err| err.to_string());
+    }
+
+    let mut game = match puzzle {
+        Some(input) => {
+            BingoGame::from_puzzle(&input, &patterns).with_names(&players.unwrap_or_default())?
+        }
+        None => {
+            let players =
+                players.unwrap_or_else(|| vec!["Player 1".to_owned(), "Player 2".to_owned()]);
+            BingoGame::random(&players, &patterns, &mut rng)
+        }
+    };
+
+    game.play(io::stdin().lock(), io::stdout(), highlight)
+        .map_err(|err| err.to_string())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("bingo: {err}");
+        process::exit(1);
+    }
+}
@@ -27,6 +27,38 @@ pub fn part1_windows(input: &[i32]) -> usize {
         .count()
 }
 
+// number of readings compared per inner loop, small enough to stay in registers
+// and large enough for the compiler to vectorise the comparison
+const CHUNK_SIZE: usize = 64;
+
+#[aoc(day1, part1, chunked)]
+pub fn part1_chunked(input: &[i32]) -> usize {
+    if input.len() < 2 {
+        return 0;
+    }
+
+    let mut prev_chunks = input[..input.len() - 1].chunks_exact(CHUNK_SIZE);
+    let mut next_chunks = input[1..].chunks_exact(CHUNK_SIZE);
+
+    let mut count = 0;
+    for (prev, next) in prev_chunks.by_ref().zip(next_chunks.by_ref()) {
+        // branchless count per chunk, a u32 accumulator keeps the lanes narrow
+        count += prev
+            .iter()
+            .zip(next)
+            .map(|(a, b)| (b > a) as u32)
+            .sum::<u32>() as usize;
+    }
+
+    count
+        + prev_chunks
+            .remainder()
+            .iter()
+            .zip(next_chunks.remainder())
+            .filter(|(a, b)| b > a)
+            .count()
+}
+
 #[aoc(day1, part2)]
 pub fn part2(input: &[i32]) -> usize {
     let window_sums: Vec<i32> = input.windows(3).map(|x| x.iter().sum()).collect();
@@ -66,6 +98,32 @@ mod tests {
         let input = parse(TEST_INPUT);
         assert_eq!(7, part1(&input));
         assert_eq!(7, part1_windows(&input));
+        assert_eq!(7, part1_chunked(&input));
+    }
+
+    #[test]
+    fn test_part1_chunked() {
+        // simple lcg so the test does not need a rand dependency
+        let mut state: u32 = 2021;
+        let input: Vec<i32> = (0..10 * CHUNK_SIZE + 7)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as i32 % 1000
+            })
+            .collect();
+
+        for len in [
+            0,
+            1,
+            2,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            3 * CHUNK_SIZE + 2,
+            input.len(),
+        ] {
+            assert_eq!(part1(&input[..len]), part1_chunked(&input[..len]));
+            assert_eq!(part1_windows(&input[..len]), part1_chunked(&input[..len]));
+        }
     }
     #[test]
     fn test_part2() {
@@ -1,91 +1,820 @@
-#[derive(Debug, PartialEq)]
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Direction {
     Forward(i32),
     Down(i32),
     Up(i32),
+    Back(i32),
+    Level,
 }
 
-impl Direction {
-    fn from_command(command_string: &str) -> Direction {
-        let mut split = command_string.trim().splitn(2, " ");
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(command_string: &str) -> Result<Self, Self::Err> {
+        let mut split = command_string.split_whitespace();
 
-        let dir = split.next().unwrap();
-        let num: i32 = split.next().unwrap().parse().unwrap();
+        let dir = split.next().ok_or("missing command")?;
+        let arg = split.next();
 
-        match dir {
-            "forward" => Direction::Forward(num),
-            "down" => Direction::Down(num),
-            "up" => Direction::Up(num),
-            &_ => panic!("invalid direction"),
+        let direction = match (dir, arg) {
+            ("level", None) | ("reset", Some("aim")) => Direction::Level,
+            ("forward" | "down" | "up" | "back", Some(arg)) => {
+                let num: i32 = arg
+                    .parse()
+                    .map_err(|err| format!("invalid distance '{arg}': {err}"))?;
+                match dir {
+                    "forward" => Direction::Forward(num),
+                    "down" => Direction::Down(num),
+                    "up" => Direction::Up(num),
+                    _ => Direction::Back(num),
+                }
+            }
+            ("forward" | "down" | "up" | "back", None) => {
+                return Err(format!("'{dir}' needs a distance"))
+            }
+            _ => return Err(format!("unknown command '{}'", command_string.trim())),
+        };
+
+        if split.next().is_some() {
+            return Err(format!("trailing input in '{}'", command_string.trim()));
         }
+
+        Ok(direction)
     }
 }
 
-#[derive(Debug)]
-struct Position {
-    depth: i32,
+/// A command script failed to parse, `line` is 1-based.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn too_many_commands(line: usize) -> ParseError {
+    ParseError::new(
+        line,
+        format!("program expands to more than {MAX_COMMANDS} commands"),
+    )
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Statement {
+    Command(Direction),
+    Repeat(usize, Vec<Statement>),
+}
+
+impl Statement {
+    fn unroll(&self, directions: &mut Vec<Direction>) {
+        match self {
+            Statement::Command(direction) => directions.push(*direction),
+            Statement::Repeat(count, body) => {
+                for _ in 0..*count {
+                    body.iter().for_each(|x| x.unroll(directions));
+                }
+            }
+        }
+    }
+}
+
+/// Most commands a program may expand to, so `directions` stays in memory.
+pub const MAX_COMMANDS: usize = 10_000_000;
+
+/// Parsed submarine command script.
+///
+/// One command per line, `#` starts a comment and blocks of the form
+/// `repeat N {` ... `}` may be nested. Programs expanding to more than
+/// `MAX_COMMANDS` commands are rejected.
+#[derive(Debug, PartialEq)]
+pub struct Program(Vec<Statement>);
+
+impl Program {
+    pub fn statements(&self) -> &[Statement] {
+        &self.0
+    }
+
+    /// Flattens the program into the sequence of directions it executes.
+    pub fn directions(&self) -> Vec<Direction> {
+        let mut directions = Vec::new();
+        self.0.iter().for_each(|x| x.unroll(&mut directions));
+        directions
+    }
+
+    /// Parses statements up to the closing `}` of the block opened at
+    /// `opened_at`, along with the number of commands they expand to.
+    fn parse_block<'a>(
+        lines: &mut impl Iterator<Item = (usize, &'a str)>,
+        opened_at: Option<usize>,
+    ) -> Result<(Vec<Statement>, usize), ParseError> {
+        let mut statements = Vec::new();
+        let mut commands = 0;
+
+        while let Some((line, text)) = lines.next() {
+            if text == "}" {
+                return match opened_at {
+                    Some(_) => Ok((statements, commands)),
+                    None => Err(ParseError::new(line, "unmatched '}'")),
+                };
+            }
+
+            match text.split_once(char::is_whitespace) {
+                Some(("repeat", rest)) => {
+                    let count = rest
+                        .trim()
+                        .strip_suffix('{')
+                        .ok_or_else(|| ParseError::new(line, "expected '{' after repeat count"))?
+                        .trim();
+                    let count: usize = count.parse().map_err(|err| {
+                        ParseError::new(line, format!("invalid repeat count '{count}': {err}"))
+                    })?;
+
+                    let (body, body_commands) = Self::parse_block(lines, Some(line))?;
+                    commands = count
+                        .checked_mul(body_commands)
+                        .and_then(|x| x.checked_add(commands))
+                        .filter(|x| *x <= MAX_COMMANDS)
+                        .ok_or_else(|| too_many_commands(line))?;
+                    statements.push(Statement::Repeat(count, body));
+                }
+                _ => {
+                    statements.push(Statement::Command(
+                        text.parse().map_err(|err| ParseError::new(line, err))?,
+                    ));
+                    commands += 1;
+                    if commands > MAX_COMMANDS {
+                        return Err(too_many_commands(line));
+                    }
+                }
+            }
+        }
+
+        match opened_at {
+            Some(line) => Err(ParseError::new(line, "unclosed 'repeat' block")),
+            None => Ok((statements, commands)),
+        }
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .map(|(i, x)| (i + 1, x.split('#').next().unwrap().trim()))
+            .filter(|(_, x)| !x.is_empty());
+
+        Ok(Program(Self::parse_block(&mut lines, None)?.0))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Position {
+    pub depth: i32,
+    pub horizontal: i32,
+    pub aim: i32,
+}
+
+impl Position {
+    pub fn product(&self) -> i32 {
+        self.depth * self.horizontal
+    }
+}
+
+/// Interpretation of a `Direction` as a change of the submarine's `Position`.
+pub trait MovementModel: Send + Sync {
+    fn go(&self, position: &mut Position, direction: &Direction);
+}
+
+/// Part 1: up and down change the depth directly.
+pub struct Simple;
+
+impl MovementModel for Simple {
+    fn go(&self, position: &mut Position, direction: &Direction) {
+        match direction {
+            Direction::Forward(num) => position.horizontal += num,
+            Direction::Down(num) => position.depth += num,
+            Direction::Up(num) => position.depth -= num,
+            Direction::Back(num) => position.horizontal -= num,
+            Direction::Level => {}
+        };
+    }
+}
+
+/// Part 2: up and down change the aim, moving changes the depth by the aim.
+pub struct Aim;
+
+impl MovementModel for Aim {
+    fn go(&self, position: &mut Position, direction: &Direction) {
+        match direction {
+            Direction::Forward(num) => {
+                position.horizontal += num;
+                position.depth += position.aim * num;
+            }
+
+            Direction::Down(num) => position.aim += num,
+            Direction::Up(num) => position.aim -= num,
+            Direction::Back(num) => {
+                position.horizontal -= num;
+                position.depth -= position.aim * num;
+            }
+            Direction::Level => position.aim = 0,
+        };
+    }
+}
+
+/// Aim model where every horizontal move pulls the aim `drag` closer to level.
+pub struct AimWithDrag {
+    pub drag: i32,
+}
+
+impl MovementModel for AimWithDrag {
+    fn go(&self, position: &mut Position, direction: &Direction) {
+        Aim.go(position, direction);
+
+        if let Direction::Forward(_) | Direction::Back(_) = direction {
+            position.aim -= position.aim.signum() * self.drag.min(position.aim.abs());
+        }
+    }
+}
+
+/// Wraps another model and clamps the depth to `min_depth..=max_depth`.
+pub struct DepthLimited<M: MovementModel> {
+    pub model: M,
+    pub min_depth: i32,
+    pub max_depth: i32,
+}
+
+impl<M: MovementModel> MovementModel for DepthLimited<M> {
+    fn go(&self, position: &mut Position, direction: &Direction) {
+        self.model.go(position, direction);
+        position.depth = position.depth.clamp(self.min_depth, self.max_depth);
+    }
+}
+
+/// Movement models selectable by name.
+#[derive(Default)]
+pub struct ModelRegistry(HashMap<String, Box<dyn MovementModel>>);
+
+impl ModelRegistry {
+    pub fn new() -> Self {
+        ModelRegistry(HashMap::new())
+    }
+
+    /// Registry with all models of this module.
+    pub fn builtin() -> Self {
+        let mut registry = ModelRegistry::new();
+        registry.register("simple", Simple);
+        registry.register("aim", Aim);
+        registry.register("aim_drag", AimWithDrag { drag: 1 });
+        registry.register(
+            "aim_surface_limited",
+            DepthLimited {
+                model: Aim,
+                min_depth: 0,
+                max_depth: i32::MAX,
+            },
+        );
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, model: impl MovementModel + 'static) {
+        self.0.insert(name.to_owned(), Box::new(model));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn MovementModel> {
+        self.0.get(name).map(|x| x.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|x| x.as_str())
+    }
+}
+
+/// Every position of the submarine, starting at the origin and followed by the
+/// position after each command.
+pub fn trajectory<'a>(
+    input: &'a [Direction],
+    model: &'a dyn MovementModel,
+) -> impl Iterator<Item = Position> + 'a {
+    std::iter::once(Position::default()).chain(input.iter().scan(
+        Position::default(),
+        move |pos, x| {
+            model.go(pos, x);
+            Some(*pos)
+        },
+    ))
+}
+
+pub fn run(input: &[Direction], model: &dyn MovementModel) -> Position {
+    trajectory(input, model).last().unwrap()
+}
+
+/// Limits a submarine has to stay within while executing commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraints {
+    pub min_depth: i32,
+    pub max_depth: i32,
+    /// Largest allowed absolute aim.
+    pub max_aim: i32,
+}
+
+impl Default for Constraints {
+    /// Only forbids surfacing above depth 0.
+    fn default() -> Self {
+        Constraints {
+            min_depth: 0,
+            max_depth: i32::MAX,
+            max_aim: i32::MAX,
+        }
+    }
+}
+
+impl Constraints {
+    fn check(&self, position: &Position) -> Option<ViolationKind> {
+        if position.depth < self.min_depth {
+            Some(ViolationKind::TooShallow)
+        } else if position.depth > self.max_depth {
+            Some(ViolationKind::TooDeep)
+        } else if position.aim.unsigned_abs() > self.max_aim.unsigned_abs() {
+            Some(ViolationKind::AimTooSteep)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViolationKind {
+    TooShallow,
+    TooDeep,
+    AimTooSteep,
+}
+
+/// First command that moved the submarine outside its `Constraints`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SafetyViolation {
+    pub index: usize,
+    pub direction: Direction,
+    /// Position right after executing the violating command.
+    pub position: Position,
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for SafetyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            ViolationKind::TooShallow => "above minimum depth",
+            ViolationKind::TooDeep => "below maximum depth",
+            ViolationKind::AimTooSteep => "aim too steep",
+        };
+        write!(
+            f,
+            "command {} ({:?}) {}: horizontal {}, depth {}, aim {}",
+            self.index,
+            self.direction,
+            reason,
+            self.position.horizontal,
+            self.position.depth,
+            self.position.aim
+        )
+    }
+}
+
+impl std::error::Error for SafetyViolation {}
+
+/// Like `run`, but stops at the first command violating `constraints`.
+pub fn run_checked(
+    input: &[Direction],
+    model: &dyn MovementModel,
+    constraints: &Constraints,
+) -> Result<Position, SafetyViolation> {
+    let mut pos = Position::default();
+    for (index, direction) in input.iter().enumerate() {
+        model.go(&mut pos, direction);
+        if let Some(kind) = constraints.check(&pos) {
+            return Err(SafetyViolation {
+                index,
+                direction: *direction,
+                position: pos,
+                kind,
+            });
+        }
+    }
+    Ok(pos)
+}
+
+pub fn trajectory_csv(trajectory: impl Iterator<Item = Position>) -> String {
+    let mut csv = String::from("step,horizontal,depth,aim\n");
+    for (step, pos) in trajectory.enumerate() {
+        csv += &format!("{},{},{},{}\n", step, pos.horizontal, pos.depth, pos.aim);
+    }
+    csv
+}
+
+/// Side view of several trajectories with the depth growing downwards.
+///
+/// Each trajectory is drawn with its own marker, cells shared by more than one
+/// trajectory are drawn as `*`.
+pub fn plot_ascii(trajectories: &[(char, Vec<Position>)], width: usize, height: usize) -> String {
+    assert!(width > 0 && height > 0, "plot needs at least one cell");
+
+    let positions = || trajectories.iter().flat_map(|(_, x)| x);
+    let (Some(h_min), Some(h_max)) = (
+        positions().map(|x| x.horizontal).min(),
+        positions().map(|x| x.horizontal).max(),
+    ) else {
+        return String::new();
+    };
+    let d_min = positions().map(|x| x.depth).min().unwrap();
+    let d_max = positions().map(|x| x.depth).max().unwrap();
+
+    let scale = |value: i32, min: i32, max: i32, cells: usize| {
+        let range = (max as i64 - min as i64).max(1);
+        ((value as i64 - min as i64) * (cells as i64 - 1) / range) as usize
+    };
+
+    let mut canvas = vec![vec![' '; width]; height];
+    for (marker, positions) in trajectories {
+        for pos in positions {
+            let cell = &mut canvas[scale(pos.depth, d_min, d_max, height)]
+                [scale(pos.horizontal, h_min, h_max, width)];
+            *cell = match *cell {
+                ' ' => *marker,
+                x if x == *marker => x,
+                _ => '*',
+            };
+        }
+    }
+
+    let mut plot = format!("horizontal {h_min}..{h_max}, depth {d_min}..{d_max}\n");
+    for row in canvas {
+        plot += row.iter().collect::<String>().trim_end();
+        plot.push('\n');
+    }
+    plot
+}
+
+/// Side view of the commands under both puzzle models, `s` for part 1 and `a`
+/// for part 2.
+pub fn side_view(input: &[Direction], width: usize, height: usize) -> String {
+    plot_ascii(
+        &[
+            ('s', trajectory(input, &Simple).collect()),
+            ('a', trajectory(input, &Aim).collect()),
+        ],
+        width,
+        height,
+    )
+}
+
+/// Runs the commands with the model registered as `name` in `registry`.
+pub fn run_named(input: &[Direction], registry: &ModelRegistry, name: &str) -> Option<Position> {
+    registry.get(name).map(|model| run(input, model))
+}
+
+/// `ModelRegistry::builtin`, created on first use.
+pub fn builtin_models() -> &'static ModelRegistry {
+    static MODELS: OnceLock<ModelRegistry> = OnceLock::new();
+    MODELS.get_or_init(ModelRegistry::builtin)
+}
+
+/// Effect of a command sequence under the `Aim` model as an affine transform.
+///
+/// Applying it to a position `(horizontal, depth, aim)` gives
+/// `(horizontal + self.horizontal, depth + self.depth + aim * self.aimed_horizontal,
+/// keep * aim + self.aim)` where `keep` is 0 once the sequence contains `Level`.
+/// Transforms compose associatively with `then`, so long command lists can be
+/// reduced in any chunking.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AimTransform {
     horizontal: i32,
+    depth: i32,
     aim: i32,
+    keeps_aim: bool,
+    /// Horizontal distance travelled before the first `Level`, the incoming aim
+    /// only affects the depth along this part.
+    aimed_horizontal: i32,
 }
 
-impl Position {
-    fn new() -> Position {
-        Position {
-            depth: 0,
+impl AimTransform {
+    pub fn identity() -> Self {
+        AimTransform {
             horizontal: 0,
+            depth: 0,
             aim: 0,
+            keeps_aim: true,
+            aimed_horizontal: 0,
         }
     }
 
-    fn go1(&mut self, direction: &Direction) {
-        match direction {
-            Direction::Forward(num) => self.horizontal += num,
-            Direction::Down(num) => self.depth += num,
-            Direction::Up(num) => self.depth -= num,
-        };
+    /// `self` followed by `other`.
+    pub fn then(&self, other: &AimTransform) -> Self {
+        AimTransform {
+            horizontal: self.horizontal + other.horizontal,
+            depth: self.depth + other.depth + self.aim * other.aimed_horizontal,
+            aim: if other.keeps_aim { self.aim } else { 0 } + other.aim,
+            keeps_aim: self.keeps_aim && other.keeps_aim,
+            aimed_horizontal: self.aimed_horizontal
+                + if self.keeps_aim {
+                    other.aimed_horizontal
+                } else {
+                    0
+                },
+        }
+    }
+
+    pub fn apply(&self, position: &Position) -> Position {
+        Position {
+            horizontal: position.horizontal + self.horizontal,
+            depth: position.depth + self.depth + position.aim * self.aimed_horizontal,
+            aim: if self.keeps_aim { position.aim } else { 0 } + self.aim,
+        }
+    }
+
+    pub fn from_directions(directions: &[Direction]) -> Self {
+        directions
+            .iter()
+            .fold(Self::identity(), |acc, x| acc.then(&x.into()))
     }
+}
 
-    fn go2(&mut self, direction: &Direction) {
+impl From<&Direction> for AimTransform {
+    fn from(direction: &Direction) -> Self {
+        let mut transform = AimTransform::identity();
         match direction {
             Direction::Forward(num) => {
-                self.horizontal += num;
-                self.depth += self.aim * num;
+                transform.horizontal = *num;
+                transform.aimed_horizontal = *num;
+            }
+            Direction::Back(num) => {
+                transform.horizontal = -num;
+                transform.aimed_horizontal = -num;
             }
+            Direction::Down(num) => transform.aim = *num,
+            Direction::Up(num) => transform.aim = -num,
+            Direction::Level => transform.keeps_aim = false,
+        }
+        transform
+    }
+}
 
-            Direction::Down(num) => self.aim += num,
-            Direction::Up(num) => self.aim -= num,
-        };
+const PARALLEL_CHUNK_SIZE: usize = 4096;
+
+/// Composes all commands in parallel chunks.
+pub fn reduce_parallel(input: &[Direction]) -> AimTransform {
+    input
+        .par_chunks(PARALLEL_CHUNK_SIZE)
+        .map(AimTransform::from_directions)
+        .reduce(AimTransform::identity, |a, b| a.then(&b))
+}
+
+/// Positions after every prefix of the commands under the `Aim` model.
+pub struct PrefixTable(Vec<Position>);
+
+impl PrefixTable {
+    /// Builds the table in three passes: chunk transforms in parallel, a
+    /// sequential scan over the chunk totals and the chunk contents in parallel.
+    pub fn new(input: &[Direction]) -> Self {
+        let chunk_totals: Vec<_> = input
+            .par_chunks(PARALLEL_CHUNK_SIZE)
+            .map(AimTransform::from_directions)
+            .collect();
+
+        let chunk_starts: Vec<_> = chunk_totals
+            .iter()
+            .scan(Position::default(), |pos, x| {
+                let start = *pos;
+                *pos = x.apply(pos);
+                Some(start)
+            })
+            .collect();
+
+        let mut positions = vec![Position::default(); input.len() + 1];
+        positions[1..]
+            .par_chunks_mut(PARALLEL_CHUNK_SIZE)
+            .zip(input.par_chunks(PARALLEL_CHUNK_SIZE))
+            .zip(chunk_starts.par_iter())
+            .for_each(|((positions, directions), start)| {
+                let mut pos = *start;
+                for (slot, direction) in positions.iter_mut().zip(directions) {
+                    Aim.go(&mut pos, direction);
+                    *slot = pos;
+                }
+            });
+
+        PrefixTable(positions)
     }
+
+    /// Position after executing the first `commands` commands.
+    pub fn position_after(&self, commands: usize) -> Position {
+        self.0[commands]
+    }
+}
+
+/// Search for the shortest command sequence reaching a target under the `Aim`
+/// model.
+///
+/// Forwards can be reordered by aim without changing the final depth, so only
+/// plans steering monotonically towards the target depth are searched: runs of
+/// `forward` at a fixed aim alternating with runs of `down` (or `up` for a
+/// negative depth). A run covering a distance of `n` takes `ceil(n / max)`
+/// commands.
+struct Planner {
+    max_magnitude: i64,
+}
+
+impl Planner {
+    fn cost(&self, distance: i64) -> i64 {
+        (distance + self.max_magnitude - 1) / self.max_magnitude
+    }
+
+    fn push_run(&self, plan: &mut Vec<i64>, distance: i64) {
+        let count = self.cost(distance);
+        plan.push(distance - (count - 1) * self.max_magnitude);
+        plan.extend(std::iter::repeat_n(self.max_magnitude, count as usize - 1));
+    }
+
+    fn feasible(&self, horizontal: i64, depth: i64, aim: i64, budget: i64) -> bool {
+        let forwards = self.cost(horizontal);
+        let max_aim = aim + (budget - forwards) * self.max_magnitude;
+        forwards <= budget && aim * horizontal <= depth && depth <= max_aim * horizontal
+    }
+
+    /// Tries a run of forwards, the plan holds forwards as positive and downs as
+    /// negative distances.
+    fn forward_run(
+        &self,
+        horizontal: i64,
+        depth: i64,
+        aim: i64,
+        budget: i64,
+        plan: &mut Vec<i64>,
+    ) -> bool {
+        if !self.feasible(horizontal, depth, aim, budget) {
+            return false;
+        }
+
+        let len = plan.len();
+        for distance in (1..=horizontal.min(budget * self.max_magnitude)).rev() {
+            let budget = budget - self.cost(distance);
+            let (horizontal, depth) = (horizontal - distance, depth - aim * distance);
+
+            self.push_run(plan, distance);
+            if (horizontal == 0 && depth == 0)
+                || (horizontal > 0 && self.down_run(horizontal, depth, aim, budget, plan))
+            {
+                return true;
+            }
+            plan.truncate(len);
+        }
+        false
+    }
+
+    fn down_run(
+        &self,
+        horizontal: i64,
+        depth: i64,
+        aim: i64,
+        budget: i64,
+        plan: &mut Vec<i64>,
+    ) -> bool {
+        if !self.feasible(horizontal, depth, aim, budget) {
+            return false;
+        }
+
+        let len = plan.len();
+        for distance in 1..=(depth / horizontal - aim) {
+            self.push_run(plan, distance);
+            let run_len = plan.len() - len;
+            plan[len..].iter_mut().for_each(|x| *x = -*x);
+
+            if self.forward_run(
+                horizontal,
+                depth,
+                aim + distance,
+                budget - run_len as i64,
+                plan,
+            ) {
+                return true;
+            }
+            plan.truncate(len);
+        }
+        false
+    }
+}
+
+/// Shortest sequence of `forward`, `down` and `up` commands with magnitudes of at
+/// most `max_magnitude` reaching `(horizontal, depth)` under the `Aim` model.
+///
+/// Returns `None` if the target can not be reached within `max_commands`.
+pub fn plan(
+    horizontal: i32,
+    depth: i32,
+    max_magnitude: i32,
+    max_commands: usize,
+) -> Option<Vec<Direction>> {
+    assert!(max_magnitude > 0, "commands need a positive magnitude");
+
+    if horizontal < 0 || (horizontal == 0 && depth != 0) {
+        return None;
+    }
+    if horizontal == 0 {
+        return Some(Vec::new());
+    }
+
+    let planner = Planner {
+        max_magnitude: max_magnitude as i64,
+    };
+    let (horizontal, target_depth) = (horizontal as i64, depth as i64);
+    let depth = target_depth.abs();
+
+    let mut plan = Vec::new();
+    let found = (planner.cost(horizontal)..=max_commands as i64).any(|budget| {
+        planner.forward_run(horizontal, depth, 0, budget, &mut plan)
+            || planner.down_run(horizontal, depth, 0, budget, &mut plan)
+    });
+    if !found {
+        return None;
+    }
+
+    let directions: Vec<_> = plan
+        .into_iter()
+        .map(|x| match (x > 0, target_depth < 0) {
+            (true, _) => Direction::Forward(x as i32),
+            (false, false) => Direction::Down(-x as i32),
+            (false, true) => Direction::Up(-x as i32),
+        })
+        .collect();
+
+    let reached = run(&directions, &Aim);
+    assert_eq!(
+        (horizontal as i32, target_depth as i32),
+        (reached.horizontal, reached.depth),
+        "replaying the plan missed the target"
+    );
+
+    Some(directions)
 }
 
 #[aoc_generator(day2)]
 pub fn parse(input: &str) -> Vec<Direction> {
-    input.lines().map(|x| Direction::from_command(x)).collect()
+    input
+        .parse::<Program>()
+        .unwrap_or_else(|err| panic!("Failed to parse commands: {err}"))
+        .directions()
 }
 
+/// Registered models the puzzle parts run with.
+pub const PART1_MODEL: &str = "simple";
+pub const PART2_MODEL: &str = "aim";
+
 #[aoc(day2, part1)]
 pub fn part1(input: &[Direction]) -> i32 {
-    let mut pos = Position::new();
-    for direction in input {
-        pos.go1(direction);
-    }
-
-    pos.depth * pos.horizontal
+    run_named(input, builtin_models(), PART1_MODEL)
+        .unwrap()
+        .product()
 }
 
 #[aoc(day2, part2)]
 pub fn part2(input: &[Direction]) -> i32 {
-    let mut pos = Position::new();
-    for direction in input {
-        pos.go2(direction);
-    }
+    run_named(input, builtin_models(), PART2_MODEL)
+        .unwrap()
+        .product()
+}
 
-    pos.depth * pos.horizontal
+#[aoc(day2, part2, parallel)]
+pub fn part2_parallel(input: &[Direction]) -> i32 {
+    reduce_parallel(input).apply(&Position::default()).product()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools;
 
     const TEST_INPUT: &str = "forward 5
     down 5
@@ -109,6 +838,357 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_program() {
+        let program: Program = "# descend a bit
+            down 2
+            repeat 2 {
+                forward 3 # comment after a command
+                repeat 3 {
+                    up 1
+                }
+            }
+            back 1
+            reset aim
+            level"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            &[
+                Statement::Command(Direction::Down(2)),
+                Statement::Repeat(
+                    2,
+                    vec![
+                        Statement::Command(Direction::Forward(3)),
+                        Statement::Repeat(3, vec![Statement::Command(Direction::Up(1))]),
+                    ]
+                ),
+                Statement::Command(Direction::Back(1)),
+                Statement::Command(Direction::Level),
+                Statement::Command(Direction::Level),
+            ],
+            program.statements()
+        );
+        assert_eq!(12, program.directions().len());
+    }
+
+    #[test]
+    fn test_parse_program_errors() {
+        let err = "forward 1\nsideways 2".parse::<Program>().unwrap_err();
+        assert_eq!(2, err.line);
+
+        let err = "forward 1\ndown x".parse::<Program>().unwrap_err();
+        assert_eq!(2, err.line);
+
+        let err = "up\n".parse::<Program>().unwrap_err();
+        assert_eq!(1, err.line);
+
+        let err = "forward 1\nrepeat 2 {\nup 1"
+            .parse::<Program>()
+            .unwrap_err();
+        assert_eq!(ParseError::new(2, "unclosed 'repeat' block"), err);
+
+        let err = "forward 1\n}".parse::<Program>().unwrap_err();
+        assert_eq!(ParseError::new(2, "unmatched '}'"), err);
+
+        let err = "repeat many {\n}".parse::<Program>().unwrap_err();
+        assert_eq!(1, err.line);
+
+        let err = "up 1\nrepeat 100000 {\nrepeat 100000 {\nforward 1\n}\n}"
+            .parse::<Program>()
+            .unwrap_err();
+        assert_eq!(
+            ParseError::new(2, "program expands to more than 10000000 commands"),
+            err
+        );
+
+        // the limit holds for the whole program, not only single blocks
+        let block = format!("repeat {} {{\nup 1\n}}\n", MAX_COMMANDS / 2);
+        let err = format!("{block}{block}down 1")
+            .parse::<Program>()
+            .unwrap_err();
+        assert_eq!(7, err.line);
+        assert!(format!("{block}{block}").parse::<Program>().is_ok());
+    }
+
+    #[test]
+    fn test_extended_commands() {
+        let directions = parse("down 2\nforward 3\nlevel\nforward 1\nback 2");
+        assert_eq!(2 * 2, part1(&directions));
+        assert_eq!(2 * 6, part2(&directions));
+    }
+
+    #[test]
+    fn test_models() {
+        let input = parse(TEST_INPUT);
+        assert_eq!(
+            Some(150),
+            run_named(&input, builtin_models(), "simple").map(|x| x.product())
+        );
+        assert_eq!(
+            Some(900),
+            run_named(&input, builtin_models(), "aim").map(|x| x.product())
+        );
+        assert_eq!(None, run_named(&input, builtin_models(), "teleport"));
+
+        assert_eq!(0, ModelRegistry::default().names().count());
+        assert_eq!(0, ModelRegistry::new().names().count());
+        assert_eq!(
+            vec!["aim", "aim_drag", "aim_surface_limited", "simple"],
+            ModelRegistry::builtin()
+                .names()
+                .sorted()
+                .collect::<Vec<_>>()
+        );
+        let mut registry = ModelRegistry::new();
+        registry.register("forward_only", Simple);
+        assert_eq!(None, run_named(&input, &registry, "aim"));
+        assert!(run_named(&input, &registry, "forward_only").is_some());
+
+        let input = parse("down 3\nforward 2\nup 5\nforward 4\ndown 4\nforward 1");
+        assert_eq!(
+            Position {
+                horizontal: 7,
+                depth: 6 - 8 + 2,
+                aim: 2
+            },
+            run(&input, &Aim)
+        );
+        assert_eq!(
+            Position {
+                horizontal: 7,
+                depth: 6 - 12 + 2,
+                aim: 1
+            },
+            run(&input, &AimWithDrag { drag: 1 })
+        );
+        assert_eq!(
+            Position {
+                horizontal: 7,
+                depth: 2,
+                aim: 2
+            },
+            run_named(&input, builtin_models(), "aim_surface_limited").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let input = parse(TEST_INPUT);
+        let positions: Vec<_> = trajectory(&input, &Aim).collect();
+
+        assert_eq!(input.len() + 1, positions.len());
+        assert_eq!(Position::default(), positions[0]);
+        assert_eq!(
+            Position {
+                horizontal: 13,
+                depth: 40,
+                aim: 5
+            },
+            positions[3]
+        );
+        assert_eq!(run(&input, &Aim), positions[input.len()]);
+
+        let csv = trajectory_csv(positions.into_iter());
+        assert_eq!(
+            "step,horizontal,depth,aim
+0,0,0,0
+1,5,0,0
+2,5,0,5
+3,13,40,5
+4,13,40,2
+5,13,40,10
+6,15,60,10
+",
+            csv
+        );
+    }
+
+    #[test]
+    fn test_plot_ascii() {
+        let input = parse("forward 2\ndown 1\nforward 2");
+        let expected = "horizontal 0..4, depth 0..2
+* *
+  s s
+
+    a
+";
+        assert_eq!(
+            expected,
+            plot_ascii(
+                &[
+                    ('s', trajectory(&input, &Simple).collect()),
+                    ('a', trajectory(&input, &Aim).collect()),
+                ],
+                5,
+                4
+            )
+        );
+        assert_eq!(expected, side_view(&input, 5, 4));
+        assert_eq!("", plot_ascii(&[], 5, 4));
+    }
+
+    #[test]
+    fn test_run_checked() {
+        let input = parse(TEST_INPUT);
+        assert_eq!(
+            Ok(run(&input, &Aim)),
+            run_checked(&input, &Aim, &Constraints::default())
+        );
+
+        let constraints = Constraints {
+            max_depth: 50,
+            ..Default::default()
+        };
+        assert_eq!(
+            Ok(run(&input, &Simple)),
+            run_checked(&input, &Simple, &constraints)
+        );
+        assert_eq!(
+            Err(SafetyViolation {
+                index: 5,
+                direction: Direction::Forward(2),
+                position: Position {
+                    horizontal: 15,
+                    depth: 60,
+                    aim: 10
+                },
+                kind: ViolationKind::TooDeep
+            }),
+            run_checked(&input, &Aim, &constraints)
+        );
+
+        let constraints = Constraints {
+            max_aim: 8,
+            ..Default::default()
+        };
+        let err = run_checked(&input, &Aim, &constraints).unwrap_err();
+        assert_eq!((4, ViolationKind::AimTooSteep), (err.index, err.kind));
+
+        let input = parse("forward 1\nup 1\ndown 3");
+        let err = run_checked(&input, &Simple, &Constraints::default()).unwrap_err();
+        assert_eq!((1, ViolationKind::TooShallow), (err.index, err.kind));
+        assert_eq!(
+            "command 1 (Up(1)) above minimum depth: horizontal 1, depth -1, aim 0",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_aim_transform() {
+        let input = parse(
+            "forward 3\ndown 2\nforward 4\nlevel\nback 1\nup 3\nforward 2\ndown 5\nforward 1",
+        );
+        let start = Position {
+            horizontal: 7,
+            depth: 11,
+            aim: 3,
+        };
+
+        for split in 0..=input.len() {
+            let (head, tail) = input.split_at(split);
+            let composed =
+                AimTransform::from_directions(head).then(&AimTransform::from_directions(tail));
+            assert_eq!(AimTransform::from_directions(&input), composed);
+
+            let mut expected = start;
+            input.iter().for_each(|x| Aim.go(&mut expected, x));
+            assert_eq!(expected, composed.apply(&start));
+        }
+    }
+
+    #[test]
+    fn test_parallel() {
+        let input = parse(TEST_INPUT);
+        assert_eq!(900, part2_parallel(&input));
+
+        let commands = [
+            "forward 3",
+            "down 2",
+            "up 1",
+            "forward 1",
+            "level",
+            "back 2",
+        ];
+        let long_input: Vec<_> = (0..3 * PARALLEL_CHUNK_SIZE + 5)
+            .map(|i| commands[(i * 7) % commands.len()].parse().unwrap())
+            .collect();
+        assert_eq!(
+            run(&long_input, &Aim),
+            reduce_parallel(&long_input).apply(&Position::default())
+        );
+
+        let table = PrefixTable::new(&long_input);
+        for (i, pos) in trajectory(&long_input, &Aim).enumerate() {
+            assert_eq!(pos, table.position_after(i));
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let directions = plan(15, 60, 9, 20).unwrap();
+        assert_eq!(
+            vec![
+                Direction::Down(4),
+                Direction::Forward(6),
+                Direction::Forward(9)
+            ],
+            directions
+        );
+
+        let directions = plan(7, -10, 3, 20).unwrap();
+        let pos = run(&directions, &Aim);
+        assert_eq!((7, -10), (pos.horizontal, pos.depth));
+        assert!(directions.iter().all(|x| !matches!(x, Direction::Down(_))));
+
+        assert_eq!(Some(vec![]), plan(0, 0, 9, 5));
+        assert_eq!(None, plan(0, 3, 9, 5));
+        assert_eq!(None, plan(100, 5000, 9, 10));
+    }
+
+    #[test]
+    fn test_plan_is_shortest() {
+        // breadth first search over all command sequences as reference
+        const MAX: i32 = 3;
+        const H: i32 = 6;
+        const D: i32 = 12;
+        let mut shortest = HashMap::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut queue = std::collections::VecDeque::from([(Position::default(), 0)]);
+        while let Some((pos, len)) = queue.pop_front() {
+            if pos.horizontal > H
+                || pos.depth.abs() > 3 * H * H
+                || pos.aim.abs() > 3 * H
+                || !seen.insert(pos)
+            {
+                continue;
+            }
+            shortest.entry((pos.horizontal, pos.depth)).or_insert(len);
+            for num in 1..=MAX {
+                for direction in [
+                    Direction::Forward(num),
+                    Direction::Down(num),
+                    Direction::Up(num),
+                ] {
+                    let mut next = pos;
+                    Aim.go(&mut next, &direction);
+                    queue.push_back((next, len + 1));
+                }
+            }
+        }
+
+        for horizontal in 1..=H {
+            for depth in -D..=D {
+                assert_eq!(
+                    shortest.get(&(horizontal, depth)).copied(),
+                    plan(horizontal, depth, MAX, 12).map(|x| x.len()),
+                    "target {horizontal},{depth}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(150, part1(&parse(TEST_INPUT)));
@@ -1,4 +1,7 @@
 use grid;
+use num_bigint::BigUint;
+use std::collections::BTreeMap;
+use std::fmt;
 
 enum BinaryMost {
     False,
@@ -17,6 +20,10 @@ impl BinaryMost {
             }
         }
 
+        BinaryMost::from_counts(true_count, false_count)
+    }
+
+    fn from_counts(true_count: usize, false_count: usize) -> BinaryMost {
         if true_count > false_count {
             BinaryMost::True
         } else if false_count > true_count {
@@ -26,113 +33,800 @@ impl BinaryMost {
         }
     }
 }
-impl TryInto<bool> for BinaryMost {
-    type Error = &'static str;
-    fn try_into(self) -> Result<bool, Self::Error> {
+
+/// How a column with as many ones as zeros is resolved.
+///
+/// The policy picks the most common bit, the least common bit is its inverse.
+/// `PreferOne` gives the puzzle's rules for the oxygen and CO2 ratings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieBreak {
+    PreferOne,
+    PreferZero,
+    Error,
+    /// Leaves the column out of gamma and epsilon and doesn't filter on it.
+    SkipColumn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitCriteria {
+    MostCommon,
+    LeastCommon,
+}
+
+impl BitCriteria {
+    /// Bit selected in `column`, `None` if the column is skipped.
+    fn select(
+        self,
+        most_common: &BinaryMost,
+        tie: TieBreak,
+        column: usize,
+    ) -> Result<Option<bool>, DiagnosticError> {
+        let bit = match (most_common, tie) {
+            (BinaryMost::True, _) | (BinaryMost::Equal, TieBreak::PreferOne) => true,
+            (BinaryMost::False, _) | (BinaryMost::Equal, TieBreak::PreferZero) => false,
+            (BinaryMost::Equal, TieBreak::SkipColumn) => return Ok(None),
+            (BinaryMost::Equal, TieBreak::Error) => return Err(DiagnosticError::Tie { column }),
+        };
+
+        Ok(Some(match self {
+            BitCriteria::MostCommon => bit,
+            BitCriteria::LeastCommon => !bit,
+        }))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiagnosticError {
+    Tie { column: usize },
+}
+
+impl fmt::Display for DiagnosticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            BinaryMost::False => Ok(false),
-            BinaryMost::True => Ok(true),
-            BinaryMost::Equal => Err("Equal can not be converted to bool"),
+            DiagnosticError::Tie { column } => {
+                write!(f, "column {column} has as many ones as zeros")
+            }
         }
     }
 }
 
+impl std::error::Error for DiagnosticError {}
+
 fn bool_iter_to_string(bool_iter: impl Iterator<Item = bool>) -> String {
     bool_iter.map(|x| if x { '1' } else { '0' }).collect()
 }
 
+/// Value of a rating bit string of any width.
+fn rating(bits: &str) -> BigUint {
+    BigUint::parse_bytes(bits.as_bytes(), 2).unwrap_or_default()
+}
+
 #[aoc_generator(day3)]
 pub fn parse(input: &str) -> grid::Grid<bool> {
     let cols = input.lines().next().unwrap().trim().len();
     let vec: Vec<bool> = input
         .lines()
-        .map(|l| l.trim().chars().map(|c| c == '1'))
-        .flatten()
+        .flat_map(|l| l.trim().chars().map(|c| c == '1'))
         .collect();
 
     grid::Grid::from_vec(vec, cols)
 }
 
-#[aoc(day3, part1)]
-pub fn part1(input: &grid::Grid<bool>) -> usize {
-    let gamma_str: String = bool_iter_to_string(
-        (0..input.cols()).map(|x| BinaryMost::from_iter(input.iter_col(x)).try_into().unwrap()),
-    );
+/// Bits of the rate built from the `criteria` bit of every column.
+fn column_rate(
+    input: &grid::Grid<bool>,
+    criteria: BitCriteria,
+    tie: TieBreak,
+) -> Result<String, DiagnosticError> {
+    let mut bits = Vec::with_capacity(input.cols());
+    for col in 0..input.cols() {
+        if let Some(bit) = criteria.select(&BinaryMost::from_iter(input.iter_col(col)), tie, col)? {
+            bits.push(bit);
+        }
+    }
+    Ok(bool_iter_to_string(bits.into_iter()))
+}
 
-    let epsilon_str: String = bool_iter_to_string((0..input.cols()).map(|x| {
-        !(<BinaryMost as TryInto<bool>>::try_into(BinaryMost::from_iter(input.iter_col(x)))
-            .unwrap())
-    }));
+pub fn power_consumption(
+    input: &grid::Grid<bool>,
+    tie: TieBreak,
+) -> Result<BigUint, DiagnosticError> {
+    let gamma_str = column_rate(input, BitCriteria::MostCommon, tie)?;
+    let epsilon_str = column_rate(input, BitCriteria::LeastCommon, tie)?;
 
-    let gamma = usize::from_str_radix(&gamma_str, 2).unwrap();
-    let epsilon = usize::from_str_radix(&epsilon_str, 2).unwrap();
+    let gamma = rating(&gamma_str);
+    let epsilon = rating(&epsilon_str);
 
-    gamma * epsilon
+    Ok(gamma * epsilon)
+}
+
+#[aoc(day3, part1)]
+pub fn part1(input: &grid::Grid<bool>) -> Result<BigUint, DiagnosticError> {
+    power_consumption(input, TieBreak::Error)
 }
 
-fn filter_rate<T>(grid: &grid::Grid<bool>, filter: T) -> usize
-where
-    T: Fn(&bool, &BinaryMost) -> bool,
-{
+fn filter_rate(
+    grid: &grid::Grid<bool>,
+    criteria: BitCriteria,
+    tie: TieBreak,
+) -> Result<usize, DiagnosticError> {
     let mut use_rows: Vec<_> = (0..grid.rows()).collect();
 
     for col in 0..grid.cols() {
+        if use_rows.len() == 1 {
+            break;
+        }
+
         // get the most common bit in current column
         let temp_grid = grid::Grid::from_vec(
             use_rows
                 .iter()
-                .map(|x| grid.iter_row(*x).map(|x| *x))
-                .flatten()
+                .flat_map(|x| grid.iter_row(*x).copied())
                 .collect(),
             grid.cols(),
         );
 
         let most_common = BinaryMost::from_iter(temp_grid.iter_col(col));
+        let keep = match criteria.select(&most_common, tie, col)? {
+            Some(keep) => keep,
+            None => continue,
+        };
+
+        let new_use_rows: Vec<_> = use_rows
+            .iter()
+            .copied()
+            .filter(|row| grid.get(*row, col) == Some(&keep))
+            .collect();
+
+        // never filter away the last remaining row
+        if !new_use_rows.is_empty() {
+            use_rows = new_use_rows;
+        }
+    }
+    Ok(use_rows[0])
+}
+
+pub fn life_support_rating(
+    input: &grid::Grid<bool>,
+    tie: TieBreak,
+) -> Result<BigUint, DiagnosticError> {
+    let oxygen_rate = filter_rate(input, BitCriteria::MostCommon, tie)?;
+    let co2_rate = filter_rate(input, BitCriteria::LeastCommon, tie)?;
+
+    let oxygen = rating(&bool_iter_to_string(input.iter_row(oxygen_rate).copied()));
+    let co2 = rating(&bool_iter_to_string(input.iter_row(co2_rate).copied()));
+
+    Ok(oxygen * co2)
+}
+
+/// A rating as its bit string and its value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rating {
+    pub bits: String,
+    pub value: BigUint,
+}
+
+impl Rating {
+    fn from_bits(bits: String) -> Rating {
+        Rating {
+            value: rating(&bits),
+            bits,
+        }
+    }
+}
 
-        let mut new_use_rows = use_rows.clone();
-        for row in use_rows.iter() {
-            let row_vec: Vec<_> = grid.iter_row(*row).collect();
-            let element = row_vec[col];
-            if !filter(element, &most_common) {
-                new_use_rows.remove(new_use_rows.binary_search(row).unwrap());
-                if new_use_rows.len() == 1 {
-                    break;
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ColumnCount {
+    pub zeros: usize,
+    pub ones: usize,
+}
+
+/// All intermediate results of the diagnostic, used to audit the answers.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiagnosticReport {
+    pub gamma: Rating,
+    pub epsilon: Rating,
+    pub oxygen: Rating,
+    pub co2: Rating,
+    pub column_counts: Vec<ColumnCount>,
+    /// Row selected by filtering for the oxygen rating.
+    pub oxygen_row: usize,
+    /// Row selected by filtering for the CO2 rating.
+    pub co2_row: usize,
+}
+
+impl DiagnosticReport {
+    pub fn new(input: &grid::Grid<bool>, tie: TieBreak) -> Result<Self, DiagnosticError> {
+        let column_counts = (0..input.cols())
+            .map(|col| {
+                let ones = input.iter_col(col).filter(|x| **x).count();
+                ColumnCount {
+                    zeros: input.rows() - ones,
+                    ones,
                 }
+            })
+            .collect();
+
+        let oxygen_row = filter_rate(input, BitCriteria::MostCommon, tie)?;
+        let co2_row = filter_rate(input, BitCriteria::LeastCommon, tie)?;
+        let row_rating = |row| Rating::from_bits(bool_iter_to_string(input.iter_row(row).copied()));
+
+        Ok(DiagnosticReport {
+            gamma: Rating::from_bits(column_rate(input, BitCriteria::MostCommon, tie)?),
+            epsilon: Rating::from_bits(column_rate(input, BitCriteria::LeastCommon, tie)?),
+            oxygen: row_rating(oxygen_row),
+            co2: row_rating(co2_row),
+            column_counts,
+            oxygen_row,
+            co2_row,
+        })
+    }
+
+    pub fn power_consumption(&self) -> BigUint {
+        &self.gamma.value * &self.epsilon.value
+    }
+
+    pub fn life_support_rating(&self) -> BigUint {
+        &self.oxygen.value * &self.co2.value
+    }
+}
+
+#[aoc(day3, part2)]
+pub fn part2(input: &grid::Grid<bool>) -> Result<BigUint, DiagnosticError> {
+    life_support_rating(input, TieBreak::PreferOne)
+}
+
+/// How symbols of equal frequency are resolved for arbitrary alphabets.
+///
+/// The policy picks the most common symbol, the least common symbol uses the
+/// opposite end of the symbol order. On the alphabet `'0' < '1'` this matches
+/// `TieBreak`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolTieBreak {
+    PreferGreatest,
+    PreferSmallest,
+    Error,
+    SkipColumn,
+}
+
+impl From<TieBreak> for SymbolTieBreak {
+    fn from(tie: TieBreak) -> Self {
+        match tie {
+            TieBreak::PreferOne => SymbolTieBreak::PreferGreatest,
+            TieBreak::PreferZero => SymbolTieBreak::PreferSmallest,
+            TieBreak::Error => SymbolTieBreak::Error,
+            TieBreak::SkipColumn => SymbolTieBreak::SkipColumn,
+        }
+    }
+}
+
+/// Symbol frequencies of one column, only symbols present are stored.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FrequencyTable<T: Ord>(BTreeMap<T, usize>);
+
+impl<T: Ord + Clone> FrequencyTable<T> {
+    pub fn from_symbols<'a>(iter: impl Iterator<Item = &'a T>) -> Self
+    where
+        T: 'a,
+    {
+        let mut table = BTreeMap::new();
+        for symbol in iter {
+            *table.entry(symbol.clone()).or_insert(0) += 1;
+        }
+        FrequencyTable(table)
+    }
+
+    pub fn count(&self, symbol: &T) -> usize {
+        self.0.get(symbol).copied().unwrap_or(0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&T, usize)> {
+        self.0.iter().map(|(symbol, count)| (symbol, *count))
+    }
+
+    /// Symbol selected in `column`, `None` if the column is skipped or empty.
+    pub fn select(
+        &self,
+        criteria: BitCriteria,
+        tie: SymbolTieBreak,
+        column: usize,
+    ) -> Result<Option<T>, DiagnosticError> {
+        let target = match criteria {
+            BitCriteria::MostCommon => self.0.values().max(),
+            BitCriteria::LeastCommon => self.0.values().min(),
+        };
+        let Some(target) = target else {
+            return Ok(None);
+        };
+
+        let mut candidates = self.0.iter().filter(|(_, x)| *x == target).map(|(x, _)| x);
+        if candidates.clone().count() == 1 {
+            return Ok(candidates.next().cloned());
+        }
+
+        let prefer_greatest = match tie {
+            SymbolTieBreak::PreferGreatest => true,
+            SymbolTieBreak::PreferSmallest => false,
+            SymbolTieBreak::SkipColumn => return Ok(None),
+            SymbolTieBreak::Error => return Err(DiagnosticError::Tie { column }),
+        } == (criteria == BitCriteria::MostCommon);
+
+        Ok(if prefer_greatest {
+            candidates.last()
+        } else {
+            candidates.next()
+        }
+        .cloned())
+    }
+}
+
+/// Diagnostic report over an arbitrary alphabet, e.g. hex digits.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SymbolReport<T> {
+    rows: Vec<Vec<T>>,
+    width: usize,
+}
+
+impl SymbolReport<char> {
+    pub fn parse(input: &str) -> SymbolReport<char> {
+        SymbolReport::new(input.lines().map(|l| l.trim().chars().collect()).collect())
+    }
+}
+
+impl<T: Ord + Clone> SymbolReport<T> {
+    pub fn new(rows: Vec<Vec<T>>) -> SymbolReport<T> {
+        let width = rows.first().map_or(0, |x| x.len());
+        assert!(
+            rows.iter().all(|x| x.len() == width),
+            "all rows need the same width"
+        );
+        SymbolReport { rows, width }
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.rows[row]
+    }
+
+    fn frequencies(&self, rows: &[usize], col: usize) -> FrequencyTable<T> {
+        FrequencyTable::from_symbols(rows.iter().map(|x| &self.rows[*x][col]))
+    }
+
+    pub fn column_frequencies(&self) -> Vec<FrequencyTable<T>> {
+        let all_rows: Vec<_> = (0..self.rows.len()).collect();
+        (0..self.width)
+            .map(|col| self.frequencies(&all_rows, col))
+            .collect()
+    }
+
+    /// The `criteria` symbol of every column that is not skipped.
+    pub fn column_rate(
+        &self,
+        criteria: BitCriteria,
+        tie: SymbolTieBreak,
+    ) -> Result<Vec<T>, DiagnosticError> {
+        let mut rate = Vec::with_capacity(self.width);
+        for (col, table) in self.column_frequencies().iter().enumerate() {
+            rate.extend(table.select(criteria, tie, col)?);
+        }
+        Ok(rate)
+    }
+
+    /// Index of the row left after filtering every column by its `criteria`
+    /// symbol among the remaining rows.
+    pub fn filter_rate(
+        &self,
+        criteria: BitCriteria,
+        tie: SymbolTieBreak,
+    ) -> Result<usize, DiagnosticError> {
+        let mut use_rows: Vec<_> = (0..self.rows.len()).collect();
+
+        for col in 0..self.width {
+            if use_rows.len() <= 1 {
+                break;
+            }
+            if let Some(keep) = self
+                .frequencies(&use_rows, col)
+                .select(criteria, tie, col)?
+            {
+                use_rows.retain(|x| self.rows[*x][col] == keep);
             }
         }
-        use_rows = new_use_rows;
-        if use_rows.len() == 1 {
-            break;
+        Ok(use_rows[0])
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: [Option<usize>; 2],
+    parent: Option<usize>,
+    count: usize,
+}
+
+/// Binary trie over the report rows, every node counts the rows below it.
+pub struct ReportTrie {
+    nodes: Vec<TrieNode>,
+    width: usize,
+}
+
+impl ReportTrie {
+    pub fn new(grid: &grid::Grid<bool>) -> ReportTrie {
+        let mut trie = ReportTrie::empty(grid.cols());
+        for row in 0..grid.rows() {
+            trie.insert(grid.iter_row(row).copied());
         }
+        trie
+    }
+
+    pub fn empty(width: usize) -> ReportTrie {
+        ReportTrie {
+            nodes: vec![TrieNode::default()],
+            width,
+        }
+    }
+
+    pub fn insert(&mut self, bits: impl Iterator<Item = bool>) {
+        let mut node = 0;
+        self.nodes[node].count += 1;
+        for bit in bits {
+            node = match self.nodes[node].children[bit as usize] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode {
+                        parent: Some(node),
+                        ..Default::default()
+                    });
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit as usize] = Some(child);
+                    child
+                }
+            };
+            self.nodes[node].count += 1;
+        }
+    }
+
+    /// Walks down all branches selected by `criteria` and returns the bits of
+    /// the first row reached. A skipped column keeps both branches and a branch
+    /// without rows is never taken.
+    fn filter_rate(
+        &self,
+        criteria: BitCriteria,
+        tie: TieBreak,
+    ) -> Result<Vec<bool>, DiagnosticError> {
+        let mut frontier = vec![0];
+        for col in 0..self.width {
+            let children = |bit: usize| {
+                frontier
+                    .iter()
+                    .filter_map(move |x| self.nodes[*x].children[bit])
+            };
+            let zeros: usize = children(0).map(|x| self.nodes[x].count).sum();
+            let ones: usize = children(1).map(|x| self.nodes[x].count).sum();
+
+            let keep = if zeros == 0 || ones == 0 {
+                Some(ones > 0)
+            } else {
+                criteria.select(&BinaryMost::from_counts(ones, zeros), tie, col)?
+            };
+
+            frontier = match keep {
+                Some(bit) => children(bit as usize).collect(),
+                None => children(0).chain(children(1)).collect(),
+            };
+        }
+
+        let mut bits = Vec::with_capacity(self.width);
+        let mut node = frontier[0];
+        while let Some(parent) = self.nodes[node].parent {
+            bits.push(self.nodes[parent].children[1] == Some(node));
+            node = parent;
+        }
+        bits.reverse();
+        Ok(bits)
     }
-    use_rows[0]
 }
 
-#[aoc(day3, part2)]
-pub fn part2(input: &grid::Grid<bool>) -> usize {
-    let oxygen_rate = filter_rate(input, |value, most_common| match most_common {
-        BinaryMost::False => !*value,
-        BinaryMost::True => *value,
-        BinaryMost::Equal => *value,
-    });
+#[aoc(day3, part2, trie)]
+pub fn part2_trie(input: &grid::Grid<bool>) -> Result<BigUint, DiagnosticError> {
+    let trie = ReportTrie::new(input);
+    let rating = |bits: Vec<bool>| rating(&bool_iter_to_string(bits.into_iter()));
+
+    let oxygen = trie.filter_rate(BitCriteria::MostCommon, TieBreak::PreferOne)?;
+    let co2 = trie.filter_rate(BitCriteria::LeastCommon, TieBreak::PreferOne)?;
+
+    Ok(rating(oxygen) * rating(co2))
+}
+
+/// Why a row was rejected by `StreamingDiagnostic`.
+#[derive(Debug, PartialEq)]
+pub enum RowError {
+    Empty,
+    Width { expected: usize, found: usize },
+    Symbol { column: usize, symbol: char },
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RowError::Empty => write!(f, "empty row"),
+            RowError::Width { expected, found } => {
+                write!(f, "row has {found} columns instead of {expected}")
+            }
+            RowError::Symbol { column, symbol } => {
+                write!(
+                    f,
+                    "column {column} has {symbol:?} instead of a binary digit"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+/// Column statistics updated row by row for diagnostic feeds that never end.
+///
+/// The width is taken from the first row. Rows are only kept, in a trie, if
+/// created with `with_trie`, which is needed for the life support rating.
+#[derive(Default)]
+pub struct StreamingDiagnostic {
+    column_counts: Vec<ColumnCount>,
+    rows: usize,
+    trie: Option<ReportTrie>,
+}
+
+impl StreamingDiagnostic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_trie() -> Self {
+        StreamingDiagnostic {
+            trie: Some(ReportTrie::empty(0)),
+            ..Self::default()
+        }
+    }
+
+    /// Adds a row, rejecting it without any change if it is empty or its
+    /// width differs from the first row.
+    pub fn push(&mut self, row: &[bool]) -> Result<(), RowError> {
+        if row.is_empty() {
+            return Err(RowError::Empty);
+        }
+        if self.rows == 0 {
+            self.column_counts = vec![ColumnCount { zeros: 0, ones: 0 }; row.len()];
+            if let Some(trie) = &mut self.trie {
+                trie.width = row.len();
+            }
+        } else if self.column_counts.len() != row.len() {
+            return Err(RowError::Width {
+                expected: self.column_counts.len(),
+                found: row.len(),
+            });
+        }
+
+        for (count, bit) in self.column_counts.iter_mut().zip(row) {
+            match bit {
+                true => count.ones += 1,
+                false => count.zeros += 1,
+            }
+        }
+        if let Some(trie) = &mut self.trie {
+            trie.insert(row.iter().copied());
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Adds a row of `0` and `1`, see `push`.
+    pub fn push_line(&mut self, line: &str) -> Result<(), RowError> {
+        let row = line
+            .trim()
+            .chars()
+            .enumerate()
+            .map(|(column, c)| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(RowError::Symbol { column, symbol: c }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.push(&row)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn column_counts(&self) -> &[ColumnCount] {
+        &self.column_counts
+    }
+
+    fn column_rate(&self, criteria: BitCriteria, tie: TieBreak) -> Result<Rating, DiagnosticError> {
+        let mut bits = Vec::with_capacity(self.column_counts.len());
+        for (col, count) in self.column_counts.iter().enumerate() {
+            let most_common = BinaryMost::from_counts(count.ones, count.zeros);
+            bits.extend(criteria.select(&most_common, tie, col)?);
+        }
+        Ok(Rating::from_bits(bool_iter_to_string(bits.into_iter())))
+    }
+
+    pub fn gamma(&self, tie: TieBreak) -> Result<Rating, DiagnosticError> {
+        self.column_rate(BitCriteria::MostCommon, tie)
+    }
+
+    pub fn epsilon(&self, tie: TieBreak) -> Result<Rating, DiagnosticError> {
+        self.column_rate(BitCriteria::LeastCommon, tie)
+    }
+
+    pub fn power_consumption(&self, tie: TieBreak) -> Result<BigUint, DiagnosticError> {
+        Ok(self.gamma(tie)?.value * self.epsilon(tie)?.value)
+    }
+
+    /// `None` if rows are not retained or nothing was pushed yet.
+    pub fn life_support_rating(&self, tie: TieBreak) -> Option<Result<BigUint, DiagnosticError>> {
+        let trie = self.trie.as_ref().filter(|_| self.rows > 0)?;
+        let rate = |criteria| {
+            trie.filter_rate(criteria, tie)
+                .map(|x| rating(&bool_iter_to_string(x.into_iter())))
+        };
+
+        Some(
+            rate(BitCriteria::MostCommon)
+                .and_then(|oxygen| Ok(oxygen * rate(BitCriteria::LeastCommon)?)),
+        )
+    }
+}
+
+/// Report rows as integers, as big integers when wider than 64 bits.
+#[derive(Debug, PartialEq)]
+enum PackedRows {
+    Narrow(Vec<u64>),
+    Wide(Vec<BigUint>),
+}
+
+impl PackedRows {
+    fn len(&self) -> usize {
+        match self {
+            PackedRows::Narrow(rows) => rows.len(),
+            PackedRows::Wide(rows) => rows.len(),
+        }
+    }
+
+    fn bit(&self, row: usize, bit: usize) -> bool {
+        match self {
+            PackedRows::Narrow(rows) => rows[row] >> bit & 1 == 1,
+            PackedRows::Wide(rows) => rows[row].bit(bit as u64),
+        }
+    }
+
+    fn value(&self, row: usize) -> BigUint {
+        match self {
+            PackedRows::Narrow(rows) => BigUint::from(rows[row]),
+            PackedRows::Wide(rows) => rows[row].clone(),
+        }
+    }
+}
+
+/// Diagnostic report with every row packed into an integer and every column
+/// packed into a bitset over the rows, so column counts are popcounts.
+#[derive(Debug, PartialEq)]
+pub struct PackedReport {
+    width: usize,
+    rows: PackedRows,
+    columns: Vec<Vec<u64>>,
+}
+
+impl PackedReport {
+    fn new(rows: PackedRows, width: usize) -> PackedReport {
+        let mut columns = vec![vec![0; rows.len().div_ceil(64)]; width];
+        for i in 0..rows.len() {
+            for (col, bits) in columns.iter_mut().enumerate() {
+                bits[i / 64] |= (rows.bit(i, width - 1 - col) as u64) << (i % 64);
+            }
+        }
+
+        PackedReport {
+            width,
+            rows,
+            columns,
+        }
+    }
+
+    /// Bitset selecting every row.
+    fn all_rows(&self) -> Vec<u64> {
+        let mut mask = vec![u64::MAX; self.rows.len() / 64];
+        if !self.rows.len().is_multiple_of(64) {
+            mask.push((1 << (self.rows.len() % 64)) - 1);
+        }
+        mask
+    }
+
+    fn most_common(&self, col: usize, mask: &[u64]) -> BinaryMost {
+        let (ones, total) =
+            self.columns[col]
+                .iter()
+                .zip(mask)
+                .fold((0, 0), |(ones, total), (bits, mask)| {
+                    (ones + (bits & mask).count_ones(), total + mask.count_ones())
+                });
+        BinaryMost::from_counts(ones as usize, (total - ones) as usize)
+    }
+
+    /// Index of the row selected by `criteria`.
+    fn filter_rate(&self, criteria: BitCriteria, tie: TieBreak) -> Result<usize, DiagnosticError> {
+        let mut use_rows = self.all_rows();
+
+        for col in 0..self.width {
+            let most_common = self.most_common(col, &use_rows);
+            let keep = match criteria.select(&most_common, tie, col)? {
+                Some(keep) => keep,
+                None => continue,
+            };
+
+            let filtered: Vec<_> = use_rows
+                .iter()
+                .zip(&self.columns[col])
+                .map(|(mask, bits)| mask & if keep { *bits } else { !bits })
+                .collect();
+
+            // never filter away the last remaining row
+            if filtered.iter().any(|x| *x != 0) {
+                use_rows = filtered;
+            }
+            if use_rows.iter().map(|x| x.count_ones()).sum::<u32>() == 1 {
+                break;
+            }
+        }
+
+        let (word, bits) = use_rows.iter().enumerate().find(|(_, x)| **x != 0).unwrap();
+        Ok(word * 64 + bits.trailing_zeros() as usize)
+    }
+}
 
-    let oxygen_rate_str: String = bool_iter_to_string(input.iter_row(oxygen_rate).map(|x| *x));
+#[aoc_generator(day3, part1, packed)]
+#[aoc_generator(day3, part2, packed)]
+pub fn parse_packed(input: &str) -> PackedReport {
+    let width = input.lines().next().unwrap().trim().len();
+    let lines = input.lines().map(|l| l.trim());
+    let rows = if width <= u64::BITS as usize {
+        PackedRows::Narrow(lines.map(|l| u64::from_str_radix(l, 2).unwrap()).collect())
+    } else {
+        PackedRows::Wide(
+            lines
+                .map(|l| BigUint::parse_bytes(l.as_bytes(), 2).unwrap())
+                .collect(),
+        )
+    };
 
-    println!("oxygen rating {}", &oxygen_rate_str);
+    PackedReport::new(rows, width)
+}
 
-    let co2_rate = filter_rate(input, |value, most_common| match most_common {
-        BinaryMost::False => *value,
-        BinaryMost::True => !*value,
-        BinaryMost::Equal => !*value,
-    });
+#[aoc(day3, part1, packed)]
+pub fn part1_packed(input: &PackedReport) -> Result<BigUint, DiagnosticError> {
+    let all_rows = input.all_rows();
+    let mut gamma = Vec::with_capacity(input.width);
+    for col in 0..input.width {
+        let most_common = input.most_common(col, &all_rows);
+        let bit = BitCriteria::MostCommon.select(&most_common, TieBreak::Error, col)?;
+        gamma.push(bit.unwrap());
+    }
 
-    let co2_rate_str: String = bool_iter_to_string(input.iter_row(co2_rate).map(|x| *x));
-    println!("co2 scrubber rate {}", &co2_rate_str);
+    if input.width <= u64::BITS as usize {
+        let gamma = gamma.iter().fold(0, |acc, x| acc << 1 | *x as u64);
+        let epsilon = !gamma & (u64::MAX >> (u64::BITS as usize - input.width));
+        Ok(BigUint::from(gamma as u128 * epsilon as u128))
+    } else {
+        let gamma = rating(&bool_iter_to_string(gamma.into_iter()));
+        let epsilon = ((BigUint::from(1u32) << input.width) - 1u32) ^ &gamma;
+        Ok(gamma * epsilon)
+    }
+}
 
-    let oxygen = usize::from_str_radix(&oxygen_rate_str, 2).unwrap();
-    let co2 = usize::from_str_radix(&co2_rate_str, 2).unwrap();
+#[aoc(day3, part2, packed)]
+pub fn part2_packed(input: &PackedReport) -> Result<BigUint, DiagnosticError> {
+    let oxygen = input.filter_rate(BitCriteria::MostCommon, TieBreak::PreferOne)?;
+    let co2 = input.filter_rate(BitCriteria::LeastCommon, TieBreak::PreferOne)?;
 
-    oxygen * co2
+    Ok(input.rows.value(oxygen) * input.rows.value(co2))
 }
 
 #[cfg(test)]
@@ -166,16 +860,359 @@ mod tests {
             [true, false, true, true, false]
             [true, false, true, true, true]
         ];
-        assert_eq!(expected, parse(&SHORT_TEST_INPUT));
+        assert_eq!(expected, parse(SHORT_TEST_INPUT));
+    }
+
+    #[test]
+    fn test_parse_packed() {
+        let report = parse_packed(SHORT_TEST_INPUT);
+        assert_eq!(
+            PackedRows::Narrow(vec![0b00100, 0b11110, 0b10110, 0b10111]),
+            report.rows
+        );
+        assert_eq!(vec![0b1110], report.columns[0]);
+        assert_eq!(vec![0b1000], report.columns[4]);
+        assert_eq!(vec![0b1111], report.all_rows());
     }
 
     #[test]
     fn test_part1() {
-        assert_eq!(198, part1(&parse(TEST_INPUT)));
+        assert_eq!(Ok(BigUint::from(198u32)), part1(&parse(TEST_INPUT)));
+        assert_eq!(
+            Ok(BigUint::from(198u32)),
+            part1_packed(&parse_packed(TEST_INPUT))
+        );
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(230, part2(&parse(TEST_INPUT)));
+        assert_eq!(Ok(BigUint::from(230u32)), part2(&parse(TEST_INPUT)));
+        assert_eq!(
+            Ok(BigUint::from(230u32)),
+            part2_packed(&parse_packed(TEST_INPUT))
+        );
+        assert_eq!(Ok(BigUint::from(230u32)), part2_trie(&parse(TEST_INPUT)));
+    }
+
+    #[test]
+    fn test_packed_wide_rows() {
+        let input = format!("{}\n10{}", "1".repeat(40), "1".repeat(38));
+        let expected = BigUint::parse_bytes(b"906694364709047735681025", 10);
+        assert_eq!(Ok(expected.clone().unwrap()), part2(&parse(&input)));
+        assert_eq!(Ok(expected.unwrap()), part2_packed(&parse_packed(&input)));
+
+        let input = format!(
+            "{}\n{}\n{}",
+            "10".repeat(40),
+            "10".repeat(40),
+            "01".repeat(40)
+        );
+        assert_eq!(part1(&parse(&input)), part1_packed(&parse_packed(&input)));
+    }
+
+    #[test]
+    fn test_no_row_with_selected_bit() {
+        // the least common bit of the first column is 0, which no row has
+        let input = "10\n11";
+        let expected = Ok(BigUint::from(3u32 * 2));
+        assert_eq!(expected, part2(&parse(input)));
+        assert_eq!(expected, part2_trie(&parse(input)));
+        assert_eq!(expected, part2_packed(&parse_packed(input)));
+        assert_eq!(expected, part2(&parse("11\n10")));
+    }
+
+    #[test]
+    fn test_single_row() {
+        // the least common bit of a single row is never in it
+        assert_eq!(Ok(BigUint::from(25u32)), part2(&parse("101")));
+    }
+
+    #[test]
+    fn test_diagnostic_report() {
+        let report = DiagnosticReport::new(&parse(TEST_INPUT), TieBreak::PreferOne).unwrap();
+
+        assert_eq!(
+            Rating {
+                bits: "10110".to_owned(),
+                value: BigUint::from(22u32)
+            },
+            report.gamma
+        );
+        assert_eq!("01001", report.epsilon.bits);
+        assert_eq!(BigUint::from(9u32), report.epsilon.value);
+        assert_eq!("10111", report.oxygen.bits);
+        assert_eq!(BigUint::from(23u32), report.oxygen.value);
+        assert_eq!("01010", report.co2.bits);
+        assert_eq!(BigUint::from(10u32), report.co2.value);
+
+        assert_eq!(3, report.oxygen_row);
+        assert_eq!(11, report.co2_row);
+        assert_eq!(ColumnCount { zeros: 5, ones: 7 }, report.column_counts[0]);
+        assert_eq!(5, report.column_counts.len());
+
+        assert_eq!(BigUint::from(198u32), report.power_consumption());
+        assert_eq!(BigUint::from(230u32), report.life_support_rating());
+    }
+
+    #[test]
+    fn test_symbol_report() {
+        // the binary alphabet reproduces the puzzle answers
+        let report = SymbolReport::parse(TEST_INPUT);
+        let tie = TieBreak::PreferOne.into();
+        let to_string = |x: Vec<char>| x.into_iter().collect::<String>();
+
+        assert_eq!(
+            "10110",
+            to_string(report.column_rate(BitCriteria::MostCommon, tie).unwrap())
+        );
+        assert_eq!(
+            "01001",
+            to_string(report.column_rate(BitCriteria::LeastCommon, tie).unwrap())
+        );
+        assert_eq!(3, report.filter_rate(BitCriteria::MostCommon, tie).unwrap());
+        assert_eq!(
+            11,
+            report.filter_rate(BitCriteria::LeastCommon, tie).unwrap()
+        );
+
+        let report = SymbolReport::parse(
+            "a3f
+            a3e
+            b3f
+            c1f
+            a1e",
+        );
+        let frequencies = report.column_frequencies();
+        assert_eq!(3, frequencies[0].count(&'a'));
+        assert_eq!(0, frequencies[0].count(&'0'));
+        assert_eq!(
+            vec![(&'a', 3), (&'b', 1), (&'c', 1)],
+            frequencies[0].iter().collect::<Vec<_>>()
+        );
+
+        let tie = SymbolTieBreak::PreferGreatest;
+        assert_eq!(
+            "a3f",
+            to_string(report.column_rate(BitCriteria::MostCommon, tie).unwrap())
+        );
+        assert_eq!(
+            "b1e",
+            to_string(report.column_rate(BitCriteria::LeastCommon, tie).unwrap())
+        );
+        assert_eq!(
+            "c1e",
+            to_string(
+                report
+                    .column_rate(BitCriteria::LeastCommon, SymbolTieBreak::PreferSmallest)
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            "1e",
+            to_string(
+                report
+                    .column_rate(BitCriteria::LeastCommon, SymbolTieBreak::SkipColumn)
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            Err(DiagnosticError::Tie { column: 0 }),
+            report.column_rate(BitCriteria::LeastCommon, SymbolTieBreak::Error)
+        );
+
+        assert_eq!(
+            &['a', '3', 'f'],
+            report.row(report.filter_rate(BitCriteria::MostCommon, tie).unwrap())
+        );
+        assert_eq!(
+            &['b', '3', 'f'],
+            report.row(report.filter_rate(BitCriteria::LeastCommon, tie).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut stream = StreamingDiagnostic::new();
+        let mut retained = StreamingDiagnostic::with_trie();
+        assert_eq!(None, retained.life_support_rating(TieBreak::PreferOne));
+
+        for (i, line) in TEST_INPUT.lines().enumerate() {
+            stream.push_line(line).unwrap();
+            retained.push_line(line).unwrap();
+
+            let grid = parse(
+                &TEST_INPUT
+                    .lines()
+                    .take(i + 1)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+            assert_eq!(
+                power_consumption(&grid, TieBreak::PreferOne),
+                stream.power_consumption(TieBreak::PreferOne)
+            );
+            assert_eq!(
+                Some(life_support_rating(&grid, TieBreak::PreferOne)),
+                retained.life_support_rating(TieBreak::PreferOne)
+            );
+        }
+
+        assert_eq!(12, stream.rows());
+        assert_eq!(ColumnCount { zeros: 5, ones: 7 }, stream.column_counts()[0]);
+        assert_eq!("10110", stream.gamma(TieBreak::Error).unwrap().bits);
+        assert_eq!(
+            BigUint::from(9u32),
+            stream.epsilon(TieBreak::Error).unwrap().value
+        );
+        assert_eq!(None, stream.life_support_rating(TieBreak::PreferOne));
+        assert_eq!(
+            Some(Ok(BigUint::from(230u32))),
+            retained.life_support_rating(TieBreak::PreferOne)
+        );
+    }
+
+    #[test]
+    fn test_streaming_rejects_rows() {
+        let mut stream = StreamingDiagnostic::with_trie();
+        assert_eq!(Err(RowError::Empty), stream.push_line("  "));
+        assert_eq!(Err(RowError::Empty), stream.push(&[]));
+        assert_eq!(0, stream.rows());
+
+        stream.push_line("101").unwrap();
+        assert_eq!(
+            Err(RowError::Width {
+                expected: 3,
+                found: 4
+            }),
+            stream.push_line("1010")
+        );
+        assert_eq!(
+            Err(RowError::Symbol {
+                column: 1,
+                symbol: 'x'
+            }),
+            stream.push_line("1x1")
+        );
+        assert_eq!(Err(RowError::Empty), stream.push_line(""));
+        assert_eq!(
+            Err(RowError::Width {
+                expected: 3,
+                found: 2
+            }),
+            stream.push(&[true, false])
+        );
+
+        assert_eq!(1, stream.rows());
+        assert_eq!(ColumnCount { zeros: 1, ones: 0 }, stream.column_counts()[1]);
+        assert_eq!(
+            Some(Ok(BigUint::from(25u32))),
+            stream.life_support_rating(TieBreak::PreferOne)
+        );
+    }
+
+    #[test]
+    fn test_tie_break() {
+        const TIE_INPUT: &str = "1100
+            1010
+            1111
+            0000";
+        let input = parse(TIE_INPUT);
+        let trie = ReportTrie::new(&input);
+        let packed = parse_packed(TIE_INPUT);
+
+        let cases = [
+            (TieBreak::PreferOne, "1110", "0001", "1111", "0000"),
+            (TieBreak::PreferZero, "1000", "0111", "1100", "0000"),
+            (TieBreak::SkipColumn, "10", "01", "1100", "0000"),
+        ];
+        for (tie, gamma, epsilon, oxygen, co2) in cases {
+            assert_eq!(
+                Ok(gamma.to_owned()),
+                column_rate(&input, BitCriteria::MostCommon, tie)
+            );
+            assert_eq!(
+                Ok(epsilon.to_owned()),
+                column_rate(&input, BitCriteria::LeastCommon, tie)
+            );
+
+            for (criteria, expected) in [
+                (BitCriteria::MostCommon, oxygen),
+                (BitCriteria::LeastCommon, co2),
+            ] {
+                let row = filter_rate(&input, criteria, tie).unwrap();
+                assert_eq!(expected, bool_iter_to_string(input.iter_row(row).copied()));
+                assert_eq!(
+                    expected,
+                    bool_iter_to_string(trie.filter_rate(criteria, tie).unwrap().into_iter())
+                );
+                assert_eq!(
+                    expected,
+                    format!(
+                        "{:04b}",
+                        packed
+                            .rows
+                            .value(packed.filter_rate(criteria, tie).unwrap())
+                    )
+                );
+            }
+        }
+
+        let err = Err(DiagnosticError::Tie { column: 1 });
+        assert_eq!(err, part1(&input));
+        assert_eq!(err, part1_packed(&packed));
+        assert_eq!(
+            "column 1 has as many ones as zeros",
+            part1(&input).unwrap_err().to_string()
+        );
+
+        let err = Err(DiagnosticError::Tie { column: 2 });
+        assert_eq!(err, life_support_rating(&input, TieBreak::Error));
+        assert_eq!(
+            err,
+            trie.filter_rate(BitCriteria::MostCommon, TieBreak::Error)
+                .map(|_| BigUint::default())
+        );
+    }
+
+    #[test]
+    fn test_wide_rows() {
+        // every row repeated 21 times gives 105 bit rows with the same statistics
+        let wide_input: String = TEST_INPUT
+            .lines()
+            .map(|x| x.trim().repeat(21))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let input = parse(&wide_input);
+        assert_eq!(105, input.cols());
+
+        assert_eq!(
+            Ok(rating(&"10110".repeat(21)) * rating(&"01001".repeat(21))),
+            part1(&input)
+        );
+
+        let expected = rating(&"10111".repeat(21)) * rating(&"01010".repeat(21));
+        assert!(expected.bits() > 200);
+        assert_eq!(Ok(expected.clone()), part2(&input));
+        assert_eq!(Ok(expected), part2_trie(&input));
+    }
+
+    #[test]
+    fn test_trie() {
+        let rate = |trie: &ReportTrie, criteria| {
+            bool_iter_to_string(
+                trie.filter_rate(criteria, TieBreak::PreferOne)
+                    .unwrap()
+                    .into_iter(),
+            )
+        };
+
+        let trie = ReportTrie::new(&parse(TEST_INPUT));
+        assert_eq!(12, trie.nodes[0].count);
+        assert_eq!("10111", rate(&trie, BitCriteria::MostCommon));
+        assert_eq!("01010", rate(&trie, BitCriteria::LeastCommon));
+
+        let trie = ReportTrie::new(&parse(SHORT_TEST_INPUT));
+        assert_eq!("10111", rate(&trie, BitCriteria::MostCommon));
+        assert_eq!("00100", rate(&trie, BitCriteria::LeastCommon));
     }
 }
@@ -1,10 +1,21 @@
 use grid::Grid;
-use std::{collections::VecDeque, vec};
+use itertools::Itertools;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    io::{self, BufRead, Write},
+    str::FromStr,
+    vec,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct BingoField {
     number: i32,
     checked: bool,
+    free: bool,
 }
 
 impl BingoField {
@@ -13,25 +24,173 @@ impl BingoField {
     }
 
     fn checked(&self) -> bool {
-        return self.checked;
+        self.checked
     }
 
     fn new(number: i32) -> BingoField {
         BingoField {
             number,
             checked: false,
+            free: false,
+        }
+    }
+
+    /// The free space of an American card, checked from the start.
+    fn free() -> BingoField {
+        BingoField {
+            number: 0,
+            checked: true,
+            free: true,
+        }
+    }
+}
+
+/// Rows and columns of an American 75-ball card.
+pub const AMERICAN_SIZE: usize = 5;
+/// Numbers per B-I-N-G-O column, column `c` holds `15 * c + 1..=15 * c + 15`.
+const AMERICAN_COLUMN_RANGE: i32 = 15;
+const AMERICAN_FREE: &str = "FREE";
+
+fn american_column(col: usize) -> std::ops::RangeInclusive<i32> {
+    let start = col as i32 * AMERICAN_COLUMN_RANGE + 1;
+    start..=start + AMERICAN_COLUMN_RANGE - 1
+}
+
+/// A set of cells which wins once all of them are checked.
+#[derive(Debug, PartialEq, Clone)]
+pub enum WinPattern {
+    Row,
+    Column,
+    /// Both diagonals starting in the top corners, on rectangular boards they
+    /// end at the last row or column.
+    Diagonal,
+    FourCorners,
+    /// Both diagonals at once.
+    XShape,
+    FullCard,
+    /// Arbitrary `(row, column)` cells, cells outside the board never win.
+    Mask(Vec<(usize, usize)>),
+}
+
+fn diagonal_cells(
+    rows: usize,
+    cols: usize,
+    diagonal: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    (0..rows.min(cols)).map(move |i| (i, if diagonal == 0 { i } else { cols - 1 - i }))
+}
+
+/// The patterns of the puzzle, any complete row or column.
+pub const STANDARD_PATTERNS: [WinPattern; 2] = [WinPattern::Row, WinPattern::Column];
+
+impl FromStr for WinPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        match pattern.trim() {
+            "row" => Ok(WinPattern::Row),
+            "column" => Ok(WinPattern::Column),
+            "diagonal" => Ok(WinPattern::Diagonal),
+            "corners" => Ok(WinPattern::FourCorners),
+            "x" => Ok(WinPattern::XShape),
+            "full" => Ok(WinPattern::FullCard),
+            _ => Err(format!("unknown win pattern '{}'", pattern.trim())),
         }
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum BingoResult {
     Row(usize),
     Column(usize),
+    /// 0 for the diagonal from the top left, 1 for the one from the top right.
+    Diagonal(usize),
+    FourCorners,
+    XShape,
+    FullCard,
+    /// Index of the mask pattern in the patterns that were checked.
+    Mask(usize),
     NoBingo,
 }
 
-const BINGO_BOARD_SIZE: usize = 5;
+#[derive(Debug, PartialEq)]
+pub enum BingoParseError {
+    MissingNumbers,
+    InvalidNumber {
+        line: usize,
+        value: String,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    BoardSizeMismatch {
+        board: usize,
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    MissingFreeSpace {
+        line: usize,
+    },
+    OutOfColumnRange {
+        line: usize,
+        column: char,
+        number: i32,
+    },
+    DuplicateNumber {
+        line: usize,
+        number: i32,
+    },
+}
+
+impl fmt::Display for BingoParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BingoParseError::MissingNumbers => write!(f, "missing the line of drawn numbers"),
+            BingoParseError::InvalidNumber { line, value } => {
+                write!(f, "line {line}: invalid number '{value}'")
+            }
+            BingoParseError::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(f, "line {line}: expected {expected} numbers, found {found}"),
+            BingoParseError::BoardSizeMismatch {
+                board,
+                expected,
+                found,
+            } => write!(
+                f,
+                "board {board} is {}x{}, expected {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            BingoParseError::MissingFreeSpace { line } => {
+                write!(f, "line {line}: the centre has to be {AMERICAN_FREE}")
+            }
+            BingoParseError::OutOfColumnRange {
+                line,
+                column,
+                number,
+            } => write!(f, "line {line}: {number} is not in column {column}"),
+            BingoParseError::DuplicateNumber { line, number } => {
+                write!(f, "line {line}: {number} is already on the card")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BingoParseError {}
+
+fn parse_number(value: &str, line: usize) -> Result<i32, BingoParseError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| BingoParseError::InvalidNumber {
+            line,
+            value: value.trim().to_owned(),
+        })
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct BingoBoard {
@@ -39,16 +198,50 @@ pub struct BingoBoard {
 }
 
 impl BingoBoard {
-    fn bingo(&self) -> BingoResult {
-        for row in 0..self.board.rows() {
-            if self.board.iter_row(row).all(|x| x.checked()) {
-                return BingoResult::Row(row);
-            }
-        }
+    fn all_checked(&self, mut cells: impl Iterator<Item = (usize, usize)>) -> bool {
+        cells.all(|(row, col)| self.board.get(row, col).is_some_and(|x| x.checked()))
+    }
+
+    fn diagonal(&self, diagonal: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (rows, cols) = self.board.size();
+        diagonal_cells(rows, cols, diagonal)
+    }
+
+    /// First of `patterns` that is complete on this board.
+    pub fn bingo_with(&self, patterns: &[WinPattern]) -> BingoResult {
+        let (rows, cols) = self.board.size();
 
-        for col in 0..self.board.cols() {
-            if self.board.iter_col(col).all(|x| x.checked()) {
-                return BingoResult::Column(col);
+        for (i, pattern) in patterns.iter().enumerate() {
+            let result = match pattern {
+                WinPattern::Row => (0..rows)
+                    .find(|row| self.board.iter_row(*row).all(|x| x.checked()))
+                    .map(BingoResult::Row),
+                WinPattern::Column => (0..cols)
+                    .find(|col| self.board.iter_col(*col).all(|x| x.checked()))
+                    .map(BingoResult::Column),
+                WinPattern::Diagonal => (0..2)
+                    .find(|x| self.all_checked(self.diagonal(*x)))
+                    .map(BingoResult::Diagonal),
+                WinPattern::FourCorners => self
+                    .all_checked(
+                        [(0, 0), (0, cols - 1), (rows - 1, 0), (rows - 1, cols - 1)].into_iter(),
+                    )
+                    .then_some(BingoResult::FourCorners),
+                WinPattern::XShape => self
+                    .all_checked(self.diagonal(0).chain(self.diagonal(1)))
+                    .then_some(BingoResult::XShape),
+                WinPattern::FullCard => self
+                    .board
+                    .iter()
+                    .all(|x| x.checked())
+                    .then_some(BingoResult::FullCard),
+                WinPattern::Mask(cells) => self
+                    .all_checked(cells.iter().copied())
+                    .then_some(BingoResult::Mask(i)),
+            };
+
+            if let Some(result) = result {
+                return result;
             }
         }
 
@@ -56,27 +249,195 @@ impl BingoBoard {
     }
 
     fn check(&mut self, number: i32) {
-        match self.board.iter_mut().find(|x| x.number == number) {
-            Some(x) => x.check(),
-            None => {}
+        if let Some(x) = self.board.iter_mut().find(|x| x.number == number) {
+            x.check()
+        }
+    }
+
+    /// Parses a board of any size from its `(line number, line)` pairs, the
+    /// width is taken from the first row.
+    fn from_lines<'a>(
+        lines: impl Iterator<Item = (usize, &'a str)>,
+    ) -> Result<BingoBoard, BingoParseError> {
+        let mut vec = Vec::new();
+        let mut cols = 0;
+
+        for (line, row) in lines {
+            let fields = row
+                .split_whitespace()
+                .map(|x| parse_number(x, line).map(BingoField::new))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if vec.is_empty() {
+                cols = fields.len();
+            } else if fields.len() != cols {
+                return Err(BingoParseError::RaggedRow {
+                    line,
+                    expected: cols,
+                    found: fields.len(),
+                });
+            }
+            vec.extend(fields);
         }
+
+        Ok(BingoBoard {
+            board: Grid::from_vec(vec, cols),
+        })
     }
 
-    fn from_str(str: &str, board_size: usize) -> BingoBoard {
-        let vec: Vec<_> = str
+    /// Parses a 75-ball card, optionally below a `B I N G O` header, with
+    /// `FREE` in the centre and every column within its range of numbers.
+    pub fn parse_american(input: &str) -> Result<BingoBoard, BingoParseError> {
+        let mut lines = input
             .lines()
+            .enumerate()
+            .map(|(i, x)| (i + 1, x.trim()))
+            .filter(|(_, x)| !x.is_empty())
+            .peekable();
+        lines.next_if(|(_, x)| {
+            x.split_whitespace()
+                .collect::<String>()
+                .eq_ignore_ascii_case("BINGO")
+        });
+
+        let mut vec = Vec::new();
+        let mut seen = HashSet::new();
+        let mut rows = 0;
+        for (line, row) in lines {
+            let values: Vec<_> = row.split_whitespace().collect();
+            if values.len() != AMERICAN_SIZE {
+                return Err(BingoParseError::RaggedRow {
+                    line,
+                    expected: AMERICAN_SIZE,
+                    found: values.len(),
+                });
+            }
+
+            for (col, value) in values.into_iter().enumerate() {
+                let centre = rows == AMERICAN_SIZE / 2 && col == AMERICAN_SIZE / 2;
+                if centre != value.eq_ignore_ascii_case(AMERICAN_FREE) {
+                    return Err(if centre {
+                        BingoParseError::MissingFreeSpace { line }
+                    } else {
+                        BingoParseError::InvalidNumber {
+                            line,
+                            value: value.to_owned(),
+                        }
+                    });
+                }
+                if centre {
+                    vec.push(BingoField::free());
+                    continue;
+                }
+
+                let number = parse_number(value, line)?;
+                if !american_column(col).contains(&number) {
+                    return Err(BingoParseError::OutOfColumnRange {
+                        line,
+                        column: "BINGO".as_bytes()[col] as char,
+                        number,
+                    });
+                }
+                if !seen.insert(number) {
+                    return Err(BingoParseError::DuplicateNumber { line, number });
+                }
+                vec.push(BingoField::new(number));
+            }
+            rows += 1;
+        }
+
+        if rows != AMERICAN_SIZE {
+            return Err(BingoParseError::BoardSizeMismatch {
+                board: 0,
+                expected: (AMERICAN_SIZE, AMERICAN_SIZE),
+                found: (rows, AMERICAN_SIZE),
+            });
+        }
+
+        Ok(BingoBoard {
+            board: Grid::from_vec(vec, AMERICAN_SIZE),
+        })
+    }
+
+    /// Random valid 75-ball card.
+    pub fn random_american(rng: &mut impl Rng) -> BingoBoard {
+        let columns: Vec<Vec<i32>> = (0..AMERICAN_SIZE)
+            .map(|col| {
+                american_column(col)
+                    .collect::<Vec<_>>()
+                    .choose_multiple(rng, AMERICAN_SIZE)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+
+        let fields = (0..AMERICAN_SIZE)
+            .flat_map(|row| columns.iter().map(move |x| (row, x[row])))
+            .enumerate()
+            .map(|(i, (row, number))| {
+                if row == AMERICAN_SIZE / 2 && i % AMERICAN_SIZE == AMERICAN_SIZE / 2 {
+                    BingoField::free()
+                } else {
+                    BingoField::new(number)
+                }
+            })
+            .collect();
+
+        BingoBoard {
+            board: Grid::from_vec(fields, AMERICAN_SIZE),
+        }
+    }
+
+    /// Rows and columns of the board.
+    pub fn size(&self) -> (usize, usize) {
+        self.board.size()
+    }
+
+    /// Index of every cell's number in `indices`. Like `check`, only the first
+    /// cell of a repeated number gets one.
+    fn cell_indices(&self, indices: &HashMap<i32, usize>) -> Grid<Option<usize>> {
+        let mut seen = HashSet::new();
+        let cells = self
+            .board
+            .iter()
             .map(|x| {
-                x.trim()
-                    .split(" ")
-                    .filter(|x| !x.is_empty())
-                    .map(|x| BingoField::new(x.parse().unwrap()))
+                seen.insert(x.number)
+                    .then(|| indices.get(&x.number).copied())
+                    .flatten()
             })
-            .flatten()
             .collect();
-        let grid = Grid::from_vec(vec, board_size);
-        assert_eq!(board_size, grid.rows());
+        Grid::from_vec(cells, self.board.cols())
+    }
+
+    /// Draws the board one row per line, marking checked numbers with
+    /// `highlight`.
+    pub fn render(&self, highlight: Highlight) -> String {
+        let width = self
+            .board
+            .iter()
+            .map(|x| x.number.to_string().len())
+            .max()
+            .unwrap_or(0);
 
-        BingoBoard { board: grid }
+        (0..self.board.rows())
+            .map(|row| {
+                let line: String = self
+                    .board
+                    .iter_row(row)
+                    .map(|x| match (highlight, x.checked()) {
+                        _ if x.free => format!("{AMERICAN_FREE:>0$}", width + 2),
+                        (Highlight::Brackets, true) => format!("[{:>width$}]", x.number),
+                        (Highlight::Brackets, false) => format!(" {:>width$} ", x.number),
+                        (Highlight::Ansi, true) => {
+                            format!(" \x1b[1;32m{:>width$}\x1b[0m ", x.number)
+                        }
+                        (Highlight::Ansi, false) => format!(" {:>width$} ", x.number),
+                    })
+                    .collect();
+                line.trim_end().to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     #[allow(dead_code)]
@@ -97,6 +458,170 @@ impl BingoBoard {
     }
 }
 
+/// How `BingoBoard::render` marks checked numbers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Highlight {
+    Brackets,
+    Ansi,
+}
+
+/// Board representation usable with `BingoWinnerIter`.
+pub trait Board {
+    fn check(&mut self, number: i32);
+    fn bingo_with(&self, patterns: &[WinPattern]) -> BingoResult;
+    fn sum_unchecked(&self) -> i32;
+}
+
+impl Board for BingoBoard {
+    fn check(&mut self, number: i32) {
+        BingoBoard::check(self, number)
+    }
+
+    fn bingo_with(&self, patterns: &[WinPattern]) -> BingoResult {
+        BingoBoard::bingo_with(self, patterns)
+    }
+
+    fn sum_unchecked(&self) -> i32 {
+        BingoBoard::sum_unchecked(self)
+    }
+}
+
+/// Board with the checked cells as bits of a `u64`, cell `(row, col)` is bit
+/// `row * cols + col`. The win masks of its patterns are computed up front, so
+/// a win is a few mask comparisons.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MaskBoard {
+    numbers: Vec<i32>,
+    marked: u64,
+    patterns: Vec<WinPattern>,
+    win_masks: Vec<(u64, BingoResult)>,
+    size: (usize, usize),
+}
+
+impl MaskBoard {
+    pub fn new(board: &BingoBoard) -> Option<MaskBoard> {
+        MaskBoard::with_patterns(board, &STANDARD_PATTERNS)
+    }
+
+    /// Precomputes the win masks for `patterns`, `None` if the board has more
+    /// than 64 cells.
+    pub fn with_patterns(board: &BingoBoard, patterns: &[WinPattern]) -> Option<MaskBoard> {
+        let size = board.size();
+        if size.0 * size.1 > u64::BITS as usize {
+            return None;
+        }
+
+        let marked = board
+            .board
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.checked())
+            .fold(0, |acc, (i, _)| acc | 1 << i);
+
+        Some(MaskBoard {
+            numbers: board.board.iter().map(|x| x.number).collect(),
+            marked,
+            patterns: patterns.to_vec(),
+            win_masks: MaskBoard::win_masks(size, patterns),
+            size,
+        })
+    }
+
+    fn win_masks((rows, cols): (usize, usize), patterns: &[WinPattern]) -> Vec<(u64, BingoResult)> {
+        // cells outside the board make a pattern impossible to win
+        let mask = |cells: Vec<(usize, usize)>| {
+            cells.into_iter().try_fold(0, |acc, (row, col)| {
+                (row < rows && col < cols).then_some(acc | 1 << (row * cols + col))
+            })
+        };
+
+        let mut masks = Vec::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let candidates: Vec<(Vec<_>, _)> = match pattern {
+                WinPattern::Row => (0..rows)
+                    .map(|row| {
+                        (
+                            (0..cols).map(|col| (row, col)).collect(),
+                            BingoResult::Row(row),
+                        )
+                    })
+                    .collect(),
+                WinPattern::Column => (0..cols)
+                    .map(|col| {
+                        (
+                            (0..rows).map(|row| (row, col)).collect(),
+                            BingoResult::Column(col),
+                        )
+                    })
+                    .collect(),
+                WinPattern::Diagonal => (0..2)
+                    .map(|x| {
+                        (
+                            diagonal_cells(rows, cols, x).collect(),
+                            BingoResult::Diagonal(x),
+                        )
+                    })
+                    .collect(),
+                WinPattern::FourCorners => vec![(
+                    vec![(0, 0), (0, cols - 1), (rows - 1, 0), (rows - 1, cols - 1)],
+                    BingoResult::FourCorners,
+                )],
+                WinPattern::XShape => vec![(
+                    diagonal_cells(rows, cols, 0)
+                        .chain(diagonal_cells(rows, cols, 1))
+                        .collect(),
+                    BingoResult::XShape,
+                )],
+                WinPattern::FullCard => vec![(
+                    (0..rows * cols).map(|x| (x / cols, x % cols)).collect(),
+                    BingoResult::FullCard,
+                )],
+                WinPattern::Mask(cells) => vec![(cells.clone(), BingoResult::Mask(i))],
+            };
+
+            masks.extend(
+                candidates
+                    .into_iter()
+                    .filter_map(|(cells, result)| Some((mask(cells)?, result))),
+            );
+        }
+        masks
+    }
+}
+
+impl Board for MaskBoard {
+    fn check(&mut self, number: i32) {
+        if let Some(i) = self.numbers.iter().position(|x| *x == number) {
+            self.marked |= 1 << i;
+        }
+    }
+
+    /// Uses the precomputed masks if `patterns` are the ones given on creation.
+    fn bingo_with(&self, patterns: &[WinPattern]) -> BingoResult {
+        let computed;
+        let win_masks = if patterns == self.patterns {
+            &self.win_masks
+        } else {
+            computed = MaskBoard::win_masks(self.size, patterns);
+            &computed
+        };
+
+        win_masks
+            .iter()
+            .find(|(mask, _)| self.marked & mask == *mask)
+            .map_or(BingoResult::NoBingo, |(_, result)| result.clone())
+    }
+
+    fn sum_unchecked(&self) -> i32 {
+        self.numbers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.marked & 1 << i == 0)
+            .map(|(_, x)| x)
+            .sum()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct BingoInput {
     numbers: VecDeque<i32>,
@@ -104,39 +629,245 @@ pub struct BingoInput {
 }
 
 impl BingoInput {
-    fn get_winner_iter(&self) -> BingoWinnerIter {
+    /// Puts the drawn numbers in random order.
+    pub fn shuffle_numbers(&mut self, rng: &mut impl Rng) {
+        self.numbers.make_contiguous().shuffle(rng);
+    }
+
+    fn get_winner_iter_with(&self, patterns: &[WinPattern]) -> BingoWinnerIter<BingoBoard> {
         BingoWinnerIter {
-            bingo_input: self.clone(),
+            numbers: self.numbers.clone(),
+            bingo_boards: self.bingo_boards.clone(),
+            patterns: patterns.to_vec(),
+        }
+    }
+
+    fn get_mask_winner_iter_with(
+        &self,
+        patterns: &[WinPattern],
+    ) -> Result<BingoWinnerIter<MaskBoard>, BingoError> {
+        let bingo_boards = self
+            .bingo_boards
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                MaskBoard::with_patterns(x, patterns).ok_or(BingoError::BoardTooLarge {
+                    board: i,
+                    cells: x.size().0 * x.size().1,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(BingoWinnerIter {
+            numbers: self.numbers.clone(),
+            bingo_boards,
+            patterns: patterns.to_vec(),
+        })
+    }
+}
+
+/// When and with what score a board wins.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BoardWin {
+    pub board: usize,
+    /// Index of the winning draw.
+    pub turn: usize,
+    pub number: i32,
+    pub score: i32,
+}
+
+/// Every board ordered by its winning turn, boards winning on the same turn are
+/// ordered by index.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BingoRanking {
+    pub winners: Vec<BoardWin>,
+    pub never_winning: Vec<usize>,
+}
+
+impl BingoInput {
+    /// Computes the win of `board` without simulating the draws: a row or
+    /// column is complete at the latest draw of its numbers and the board wins
+    /// with the earliest complete row or column.
+    pub fn board_win(&self, board: usize) -> Option<BoardWin> {
+        let mut draw_turns = HashMap::new();
+        for (turn, number) in self.numbers.iter().enumerate() {
+            draw_turns.entry(*number).or_insert(turn);
+        }
+        self.board_win_with(board, &draw_turns)
+    }
+
+    fn board_win_with(&self, board: usize, draw_turns: &HashMap<i32, usize>) -> Option<BoardWin> {
+        let grid = &self.bingo_boards[board].board;
+        let turns = self.bingo_boards[board].cell_indices(draw_turns);
+
+        let line_turn = |mut line: Box<dyn Iterator<Item = &Option<usize>> + '_>| {
+            line.try_fold(0, |acc, x| Some(acc.max((*x)?)))
+        };
+        let turn = (0..turns.rows())
+            .filter_map(|row| line_turn(Box::new(turns.iter_row(row))))
+            .chain((0..turns.cols()).filter_map(|col| line_turn(Box::new(turns.iter_col(col)))))
+            .min()?;
+
+        let number = self.numbers[turn];
+        let unchecked: i32 = grid
+            .iter()
+            .zip(turns.iter())
+            .filter(|(_, x)| x.is_none_or(|x| x > turn))
+            .map(|(field, _)| field.number)
+            .sum();
+
+        Some(BoardWin {
+            board,
+            turn,
+            number,
+            score: unchecked * number,
+        })
+    }
+
+    pub fn ranking(&self) -> BingoRanking {
+        let mut draw_turns = HashMap::new();
+        for (turn, number) in self.numbers.iter().enumerate() {
+            draw_turns.entry(*number).or_insert(turn);
         }
+
+        let mut winners = Vec::new();
+        let mut never_winning = Vec::new();
+        for board in 0..self.bingo_boards.len() {
+            match self.board_win_with(board, &draw_turns) {
+                Some(win) => winners.push(win),
+                None => never_winning.push(board),
+            }
+        }
+        winners.sort_by_key(|x| x.turn);
+
+        BingoRanking {
+            winners,
+            never_winning,
+        }
+    }
+}
+
+/// One draw of a replay with the state of every board after it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplayTurn {
+    pub number: i32,
+    pub boards: Vec<BingoBoard>,
+    /// Boards that got their bingo with this draw.
+    pub winners: Vec<usize>,
+    /// Boards that got their bingo with this or an earlier draw.
+    pub won: Vec<bool>,
+}
+
+impl ReplayTurn {
+    pub fn render(&self, highlight: Highlight) -> String {
+        let mut out = format!("Drawn: {}\n", self.number);
+        for (index, board) in self.boards.iter().enumerate() {
+            let status = if self.winners.contains(&index) {
+                " - BINGO!"
+            } else if self.won[index] {
+                " - won"
+            } else {
+                ""
+            };
+            out += &format!(
+                "\nBoard {}{}\n{}\n",
+                index + 1,
+                status,
+                board.render(highlight)
+            );
+        }
+        out
+    }
+}
+
+/// Shows the turns one at a time, moving on for every line read from `input`
+/// until the turns run out, `input` ends or reads `q`.
+pub fn step_through(
+    turns: &[ReplayTurn],
+    mut input: impl BufRead,
+    mut output: impl Write,
+    highlight: Highlight,
+) -> io::Result<()> {
+    let mut line = String::new();
+    for (i, turn) in turns.iter().enumerate() {
+        writeln!(output, "Turn {}/{}", i + 1, turns.len())?;
+        writeln!(output, "{}", turn.render(highlight))?;
+        if i + 1 == turns.len() {
+            break;
+        }
+
+        write!(output, "Press enter for the next turn, q to quit: ")?;
+        output.flush()?;
+        line.clear();
+        if input.read_line(&mut line)? == 0 || line.trim() == "q" {
+            return Ok(());
+        }
+        writeln!(output)?;
+    }
+
+    writeln!(output, "End of replay")
+}
+
+impl BingoInput {
+    /// Plays the game until every board has won or the numbers run out,
+    /// recording each draw. Boards that have won keep getting marked.
+    pub fn replay_with(&self, patterns: &[WinPattern]) -> Vec<ReplayTurn> {
+        let mut boards = self.bingo_boards.clone();
+        let mut won = vec![false; boards.len()];
+        let mut turns = Vec::new();
+
+        for &number in &self.numbers {
+            if won.iter().all(|x| *x) {
+                break;
+            }
+
+            let mut winners = Vec::new();
+            for (index, board) in boards.iter_mut().enumerate() {
+                board.check(number);
+                if !won[index] && board.bingo_with(patterns) != BingoResult::NoBingo {
+                    won[index] = true;
+                    winners.push(index);
+                }
+            }
+
+            turns.push(ReplayTurn {
+                number,
+                boards: boards.clone(),
+                winners,
+                won: won.clone(),
+            });
+        }
+
+        turns
     }
 }
 
-struct BingoWinnerIter {
-    bingo_input: BingoInput,
+struct BingoWinnerIter<B: Board> {
+    numbers: VecDeque<i32>,
+    bingo_boards: Vec<B>,
+    patterns: Vec<WinPattern>,
 }
 
-impl Iterator for BingoWinnerIter {
-    type Item = (i32, Vec<BingoBoard>);
+impl<B: Board> Iterator for BingoWinnerIter<B> {
+    type Item = (i32, Vec<B>);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if self.bingo_input.bingo_boards.is_empty() {
+            if self.bingo_boards.is_empty() {
                 return None;
             }
 
-            if let Some(num) = self.bingo_input.numbers.pop_front() {
-                for board in self.bingo_input.bingo_boards.iter_mut() {
+            if let Some(num) = self.numbers.pop_front() {
+                for board in self.bingo_boards.iter_mut() {
                     board.check(num);
                 }
 
-                let mut winner_boards: Vec<BingoBoard> = Vec::new();
+                let mut winner_boards: Vec<B> = Vec::new();
                 let mut i = 0;
 
-                while i < self.bingo_input.bingo_boards.len() {
-                    if let BingoResult::Column(_) | BingoResult::Row(_) =
-                        self.bingo_input.bingo_boards[i].bingo()
-                    {
-                        winner_boards.push(self.bingo_input.bingo_boards.remove(i));
+                while i < self.bingo_boards.len() {
+                    if self.bingo_boards[i].bingo_with(&self.patterns) != BingoResult::NoBingo {
+                        winner_boards.push(self.bingo_boards.remove(i));
                     } else {
                         i += 1;
                     }
@@ -152,74 +883,830 @@ impl Iterator for BingoWinnerIter {
     }
 }
 
-#[aoc_generator(day4)]
-pub fn parse(input: &str) -> BingoInput {
-    let mut line_iter = input.lines();
+/// Bingo engine with an index from every number to the cells holding it and
+/// mark counters per row and column, so a draw only touches the boards that
+/// contain the number. Wins on `STANDARD_PATTERNS` like `BingoWinnerIter`.
+pub struct IndexedBingo {
+    numbers: VecDeque<i32>,
+    /// `(board, row, column)` of every number, in board order.
+    cells: HashMap<i32, Vec<(usize, usize, usize)>>,
+    sizes: Vec<(usize, usize)>,
+    row_marks: Vec<Vec<usize>>,
+    col_marks: Vec<Vec<usize>>,
+    unchecked_sums: Vec<i32>,
+    won: Vec<bool>,
+    remaining: usize,
+}
 
-    let numbers: VecDeque<i32> = line_iter
-        .next()
-        .unwrap()
-        .trim()
-        .split(",")
-        .map(|x| {
-            x.trim()
-                .parse()
-                .unwrap_or_else(|err| panic!("Failed to parse {x} with err {err}"))
-        })
-        .collect();
+impl IndexedBingo {
+    pub fn new(input: &BingoInput) -> Self {
+        let boards = &input.bingo_boards;
+        let mut engine = IndexedBingo {
+            numbers: input.numbers.clone(),
+            cells: HashMap::new(),
+            sizes: boards.iter().map(|x| x.size()).collect(),
+            row_marks: boards.iter().map(|x| vec![0; x.board.rows()]).collect(),
+            col_marks: boards.iter().map(|x| vec![0; x.board.cols()]).collect(),
+            unchecked_sums: boards.iter().map(|x| x.sum_unchecked()).collect(),
+            won: vec![false; boards.len()],
+            remaining: boards.len(),
+        };
+
+        for (i, board) in boards.iter().enumerate() {
+            let mut seen = HashSet::new();
+            for (pos, field) in board.board.iter().enumerate() {
+                let (row, col) = (pos / board.board.cols(), pos % board.board.cols());
+                if field.checked() {
+                    engine.row_marks[i][row] += 1;
+                    engine.col_marks[i][col] += 1;
+                } else if seen.insert(field.number) {
+                    // like BingoBoard::check only the first cell of a number is marked
+                    engine
+                        .cells
+                        .entry(field.number)
+                        .or_default()
+                        .push((i, row, col));
+                }
+            }
+        }
+        engine
+    }
+
+    pub fn unchecked_sum(&self, board: usize) -> i32 {
+        self.unchecked_sums[board]
+    }
+}
+
+impl Iterator for IndexedBingo {
+    /// The drawn number and the indices of the boards winning with it.
+    type Item = (i32, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            let num = self.numbers.pop_front()?;
+            let mut winners = Vec::new();
+
+            // a number drawn again has nothing left to mark
+            for (board, row, col) in self.cells.remove(&num).into_iter().flatten() {
+                if self.won[board] {
+                    continue;
+                }
+
+                self.unchecked_sums[board] -= num;
+                self.row_marks[board][row] += 1;
+                self.col_marks[board][col] += 1;
+
+                let (rows, cols) = self.sizes[board];
+                if self.row_marks[board][row] == cols || self.col_marks[board][col] == rows {
+                    self.won[board] = true;
+                    self.remaining -= 1;
+                    winners.push(board);
+                }
+            }
+
+            if !winners.is_empty() {
+                return Some((num, winners));
+            }
+        }
+        None
+    }
+}
+
+/// Parses the drawn numbers followed by blank line separated boards of any
+/// size. With `uniform_size` all boards need the size of the first board.
+pub fn parse_boards(input: &str, uniform_size: bool) -> Result<BingoInput, BingoParseError> {
+    let mut line_iter = input.lines().enumerate().map(|(i, x)| (i + 1, x.trim()));
+
+    let (line, numbers) = line_iter.next().ok_or(BingoParseError::MissingNumbers)?;
+    let numbers = numbers
+        .split(',')
+        .map(|x| parse_number(x, line))
+        .collect::<Result<VecDeque<_>, _>>()?;
 
     let mut boards: Vec<BingoBoard> = vec![];
+    let mut line_iter = line_iter.peekable();
 
-    while line_iter.next().is_some() {
-        let board_str: String = line_iter
-            .by_ref()
-            .take(BINGO_BOARD_SIZE)
-            .collect::<Vec<_>>()
-            .join("\n");
+    loop {
+        while line_iter.next_if(|(_, x)| x.is_empty()).is_some() {}
+        if line_iter.peek().is_none() {
+            break;
+        }
 
-        boards.push(BingoBoard::from_str(&board_str, BINGO_BOARD_SIZE));
+        let mut block = Vec::new();
+        while let Some(line) = line_iter.next_if(|(_, x)| !x.is_empty()) {
+            block.push(line);
+        }
+        let board = BingoBoard::from_lines(block.into_iter())?;
+
+        if let Some(first) = boards.first().filter(|_| uniform_size) {
+            if first.size() != board.size() {
+                return Err(BingoParseError::BoardSizeMismatch {
+                    board: boards.len(),
+                    expected: first.size(),
+                    found: board.size(),
+                });
+            }
+        }
+        boards.push(board);
     }
 
-    BingoInput {
+    Ok(BingoInput {
         numbers,
         bingo_boards: boards,
-    }
+    })
 }
 
-#[aoc(day4, part1)]
-pub fn part1(input: &BingoInput) -> i32 {
-    let mut winner_iter = input.get_winner_iter();
+/// Game where the caller draws numbers for the boards of several players.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BingoGame {
+    players: Vec<String>,
+    boards: Vec<BingoBoard>,
+    draws: VecDeque<i32>,
+    patterns: Vec<WinPattern>,
+    won: Vec<bool>,
+    /// Calls numbers with their B-I-N-G-O column.
+    american: bool,
+}
 
-    if let Some((winning_number, boards)) = winner_iter.next() {
-        assert_eq!(1, boards.len());
-        boards[0].sum_unchecked() * winning_number
-    } else {
-        -1
+impl BingoGame {
+    pub fn new(
+        players: Vec<(String, BingoBoard)>,
+        draws: impl IntoIterator<Item = i32>,
+        patterns: &[WinPattern],
+    ) -> BingoGame {
+        let (players, boards): (Vec<_>, Vec<_>) = players.into_iter().unzip();
+        BingoGame {
+            won: vec![false; boards.len()],
+            players,
+            boards,
+            draws: draws.into_iter().collect(),
+            patterns: patterns.to_vec(),
+            american: false,
+        }
     }
-}
 
-#[aoc(day4, part2)]
-pub fn part2(input: &BingoInput) -> i32 {
-    let winner_iter = input.get_winner_iter();
+    /// One player per board of the puzzle, drawing the puzzle's numbers.
+    pub fn from_puzzle(input: &BingoInput, patterns: &[WinPattern]) -> BingoGame {
+        let players = input
+            .bingo_boards
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (format!("Player {}", i + 1), x.clone()))
+            .collect();
+        BingoGame::new(players, input.numbers.iter().copied(), patterns)
+    }
 
-    if let Some((winning_number, boards)) = winner_iter.last() {
-        assert_eq!(1, boards.len());
-        boards[0].sum_unchecked() * winning_number
-    } else {
-        -1
+    /// Renames the first players, fails if there are more names than players.
+    pub fn with_names(mut self, names: &[String]) -> Result<BingoGame, String> {
+        if names.len() > self.players.len() {
+            return Err(format!(
+                "{} names for {} players",
+                names.len(),
+                self.players.len()
+            ));
+        }
+        self.players[..names.len()].clone_from_slice(names);
+        Ok(self)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Random American cards for `players` and the 75 balls in random order.
+    pub fn random(players: &[String], patterns: &[WinPattern], rng: &mut impl Rng) -> BingoGame {
+        let players = players
+            .iter()
+            .map(|x| (x.clone(), BingoBoard::random_american(rng)))
+            .collect();
+        let mut draws: Vec<_> = (1..=AMERICAN_SIZE as i32 * AMERICAN_COLUMN_RANGE).collect();
+        draws.shuffle(rng);
 
-    const TEST_INPUT: &str =
-        "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+        BingoGame {
+            american: true,
+            ..BingoGame::new(players, draws, patterns)
+        }
+    }
 
-    22 13 17 11  0
-     8  2 23  4 24
-    21  9 14 16  7
+    /// Everybody has won or there is nothing left to draw.
+    pub fn is_over(&self) -> bool {
+        self.draws.is_empty() || self.won.iter().all(|x| *x)
+    }
+
+    /// Draws the next number and returns it with the players who won with it.
+    pub fn draw(&mut self) -> Option<(i32, Vec<(usize, BingoResult)>)> {
+        if self.is_over() {
+            return None;
+        }
+
+        let number = self.draws.pop_front()?;
+        let mut winners = Vec::new();
+        for (player, board) in self.boards.iter_mut().enumerate() {
+            board.check(number);
+            if self.won[player] {
+                continue;
+            }
+
+            let result = board.bingo_with(&self.patterns);
+            if result != BingoResult::NoBingo {
+                self.won[player] = true;
+                winners.push((player, result));
+            }
+        }
+
+        Some((number, winners))
+    }
+
+    fn call(&self, number: i32) -> String {
+        let column = (number - 1) / AMERICAN_COLUMN_RANGE;
+        if self.american && (0..AMERICAN_SIZE as i32).contains(&column) {
+            format!("{}-{number}", "BINGO".as_bytes()[column as usize] as char)
+        } else {
+            number.to_string()
+        }
+    }
+
+    /// Shows the boards and draws a number for every line read from `input`
+    /// until the game is over, `input` ends or reads `q`.
+    pub fn play(
+        &mut self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+        highlight: Highlight,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            for (player, board) in self.players.iter().zip(&self.boards) {
+                writeln!(output, "{player}\n{}\n", board.render(highlight))?;
+            }
+
+            if self.is_over() {
+                writeln!(output, "Game over")?;
+                return Ok(());
+            }
+
+            write!(output, "Press enter to draw, q to quit: ")?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 || line.trim() == "q" {
+                return Ok(());
+            }
+
+            if let Some((number, winners)) = self.draw() {
+                writeln!(output, "\nCalled {}", self.call(number))?;
+                for (player, result) in winners {
+                    writeln!(
+                        output,
+                        "BINGO! {} wins with {result:?}",
+                        self.players[player]
+                    )?;
+                }
+                writeln!(output)?;
+            }
+        }
+    }
+}
+
+#[aoc_generator(day4)]
+pub fn parse(input: &str) -> BingoInput {
+    parse_boards(input, true).unwrap_or_else(|err| panic!("Failed to parse bingo: {err}"))
+}
+
+/// Estimated probability with its 95% Wilson score interval.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Estimate {
+    pub probability: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Estimate {
+    fn from_hits(hits: usize, trials: usize) -> Estimate {
+        const Z: f64 = 1.96;
+        if trials == 0 {
+            return Estimate {
+                probability: 0.0,
+                low: 0.0,
+                high: 1.0,
+            };
+        }
+
+        let n = trials as f64;
+        let p = hits as f64 / n;
+        let denominator = 1.0 + Z * Z / n;
+        let centre = (p + Z * Z / (2.0 * n)) / denominator;
+        let half_width = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+
+        Estimate {
+            probability: p,
+            low: (centre - half_width).max(0.0),
+            high: (centre + half_width).min(1.0),
+        }
+    }
+}
+
+/// Per board chance to win first and to win last. Boards winning on the same
+/// draw all count as winners of it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WinProbabilities {
+    pub trials: usize,
+    pub first: Vec<Estimate>,
+    pub last: Vec<Estimate>,
+}
+
+impl BingoInput {
+    /// Plays `trials` games with the numbers shuffled. Every trial gets its
+    /// own stream of the `seed`ed rng, so the result doesn't depend on how the
+    /// trials are spread over threads.
+    pub fn win_probabilities(&self, trials: usize, seed: u64) -> WinProbabilities {
+        let boards = self.bingo_boards.len();
+        let (first, last) = (0..trials)
+            .into_par_iter()
+            .map(|trial| {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                rng.set_stream(trial as u64);
+                let mut numbers: Vec<_> = self.numbers.iter().copied().collect();
+                numbers.shuffle(&mut rng);
+
+                let rounds: Vec<_> = IndexedBingo::new(&BingoInput {
+                    numbers: numbers.into(),
+                    bingo_boards: self.bingo_boards.clone(),
+                })
+                .map(|(_, winners)| winners)
+                .collect();
+
+                let mut first = vec![0; boards];
+                let mut last = vec![0; boards];
+                rounds
+                    .first()
+                    .into_iter()
+                    .flatten()
+                    .for_each(|x| first[*x] += 1);
+                rounds
+                    .last()
+                    .into_iter()
+                    .flatten()
+                    .for_each(|x| last[*x] += 1);
+                (first, last)
+            })
+            .reduce(
+                || (vec![0; boards], vec![0; boards]),
+                |(mut first, mut last), (other_first, other_last)| {
+                    first.iter_mut().zip(other_first).for_each(|(x, y)| *x += y);
+                    last.iter_mut().zip(other_last).for_each(|(x, y)| *x += y);
+                    (first, last)
+                },
+            );
+
+        WinProbabilities {
+            trials,
+            first: first
+                .into_iter()
+                .map(|x| Estimate::from_hits(x, trials))
+                .collect(),
+            last: last
+                .into_iter()
+                .map(|x| Estimate::from_hits(x, trials))
+                .collect(),
+        }
+    }
+}
+
+/// Whether a board should win before or after all the others.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Goal {
+    First,
+    Last,
+}
+
+/// Draws ending with the chosen board's bingo. `optimal` is false if the
+/// search ran out of nodes before ruling out shorter orders.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DrawOrder {
+    pub draws: Vec<i32>,
+    pub optimal: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SearchError {
+    Impossible,
+    NodeLimit,
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::Impossible => write!(f, "no draw order reaches the goal"),
+            SearchError::NodeLimit => write!(f, "search stopped before finding a draw order"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// `BingoBoard` that remembers its position in the input.
+struct NumberedBoard {
+    index: usize,
+    board: BingoBoard,
+}
+
+impl Board for NumberedBoard {
+    fn check(&mut self, number: i32) {
+        self.board.check(number)
+    }
+
+    fn bingo_with(&self, patterns: &[WinPattern]) -> BingoResult {
+        self.board.bingo_with(patterns)
+    }
+
+    fn sum_unchecked(&self) -> i32 {
+        self.board.sum_unchecked()
+    }
+}
+
+/// Rows and columns of `board` as indices into the pool of drawable numbers.
+/// Lines with a number missing from the pool or repeated on the board, which
+/// never gets checked, are left out.
+fn pool_lines(board: &BingoBoard, pool: &HashMap<i32, usize>) -> Vec<Vec<usize>> {
+    let cells = board.cell_indices(pool);
+
+    let rows =
+        (0..cells.rows()).map(|row| cells.iter_row(row).copied().collect::<Option<Vec<_>>>());
+    let cols =
+        (0..cells.cols()).map(|col| cells.iter_col(col).copied().collect::<Option<Vec<_>>>());
+    rows.chain(cols).flatten().collect()
+}
+
+/// Branch and bound over one line per other board, so that all of them win
+/// before the target completes its line with the `last` draw.
+struct LastSearch<'a> {
+    others: Vec<&'a [Vec<usize>]>,
+    target: &'a [Vec<usize>],
+    last: usize,
+    drawn: Vec<usize>,
+    in_drawn: Vec<bool>,
+    best: Option<Vec<usize>>,
+    nodes: usize,
+    max_nodes: usize,
+}
+
+impl<'a> LastSearch<'a> {
+    /// Numbers still needed for `line`, `None` if it needs the last draw.
+    fn cost(&self, line: &[usize]) -> Option<usize> {
+        if line.contains(&self.last) {
+            return None;
+        }
+        Some(line.iter().filter(|x| !self.in_drawn[**x]).count())
+    }
+
+    fn target_won(&self) -> bool {
+        self.target
+            .iter()
+            .any(|line| line.iter().all(|x| self.in_drawn[*x]))
+    }
+
+    fn search(&mut self) {
+        if self.nodes >= self.max_nodes {
+            return;
+        }
+        self.nodes += 1;
+
+        // continue with the board needing the most numbers, which also bounds
+        // the draws still to come
+        let mut next: Option<(usize, usize)> = None;
+        for (board, lines) in self.others.iter().enumerate() {
+            match lines.iter().filter_map(|x| self.cost(x)).min() {
+                None => return,
+                Some(0) => {}
+                Some(cost) => {
+                    if next.is_none_or(|(_, max)| cost > max) {
+                        next = Some((board, cost));
+                    }
+                }
+            }
+        }
+
+        let bound = self.best.as_ref().map_or(usize::MAX, Vec::len);
+        let Some((board, cost)) = next else {
+            if self.drawn.len() + 1 < bound {
+                let mut draws = self.drawn.clone();
+                draws.push(self.last);
+                self.best = Some(draws);
+            }
+            return;
+        };
+        if self.drawn.len() + cost + 1 >= bound {
+            return;
+        }
+
+        let lines: &'a [Vec<usize>] = self.others[board];
+        let mut lines: Vec<_> = lines
+            .iter()
+            .filter_map(|x| Some((self.cost(x)?, x)))
+            .collect();
+        lines.sort_by_key(|(cost, _)| *cost);
+
+        for (_, line) in lines {
+            let len = self.drawn.len();
+            for &x in line {
+                if !self.in_drawn[x] {
+                    self.in_drawn[x] = true;
+                    self.drawn.push(x);
+                }
+            }
+
+            if !self.target_won() {
+                self.search();
+            }
+
+            for x in self.drawn.drain(len..) {
+                self.in_drawn[x] = false;
+            }
+        }
+    }
+}
+
+impl BingoInput {
+    /// Shortest order of the drawn numbers after which `board` wins on its own,
+    /// before or after all other boards depending on `goal`. Winning last
+    /// needs a search, which gives up after `max_nodes` nodes.
+    pub fn draw_order_for(
+        &self,
+        board: usize,
+        goal: Goal,
+        max_nodes: usize,
+    ) -> Result<DrawOrder, SearchError> {
+        let pool: Vec<_> = self.numbers.iter().unique().copied().collect();
+        let indices: HashMap<_, _> = pool.iter().enumerate().map(|(i, x)| (*x, i)).collect();
+        let lines: Vec<_> = self
+            .bingo_boards
+            .iter()
+            .map(|x| pool_lines(x, &indices))
+            .collect();
+        let others: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != board)
+            .map(|(_, x)| &x[..])
+            .collect();
+
+        let (draws, optimal) = match goal {
+            Goal::First => {
+                // another board wins no later if one of its lines is within
+                // the drawn line
+                let line = lines[board]
+                    .iter()
+                    .filter(|line| {
+                        !others
+                            .iter()
+                            .flat_map(|x| x.iter())
+                            .any(|other| other.iter().all(|x| line.contains(x)))
+                    })
+                    .min_by_key(|x| x.len())
+                    .ok_or(SearchError::Impossible)?;
+                (line.clone(), true)
+            }
+            Goal::Last => {
+                let mut search = LastSearch {
+                    others,
+                    target: &lines[board],
+                    last: 0,
+                    drawn: Vec::new(),
+                    in_drawn: vec![false; pool.len()],
+                    best: None,
+                    nodes: 0,
+                    max_nodes,
+                };
+                for line in &lines[board] {
+                    for &last in line {
+                        search.last = last;
+                        search.drawn = line.iter().copied().filter(|x| *x != last).collect();
+                        search.in_drawn.iter_mut().for_each(|x| *x = false);
+                        search.drawn.iter().for_each(|x| search.in_drawn[*x] = true);
+                        if !search.target_won() {
+                            search.search();
+                        }
+                    }
+                }
+
+                let optimal = search.nodes < search.max_nodes;
+                match search.best {
+                    Some(draws) => (draws, optimal),
+                    None if optimal => return Err(SearchError::Impossible),
+                    None => return Err(SearchError::NodeLimit),
+                }
+            }
+        };
+        let draws: Vec<_> = draws.iter().map(|x| pool[*x]).collect();
+
+        let mut winner_iter = BingoWinnerIter {
+            numbers: draws.iter().copied().collect(),
+            bingo_boards: self
+                .bingo_boards
+                .iter()
+                .enumerate()
+                .map(|(index, x)| NumberedBoard {
+                    index,
+                    board: x.clone(),
+                })
+                .collect(),
+            patterns: STANDARD_PATTERNS.to_vec(),
+        };
+        let winner = match goal {
+            Goal::First => winner_iter.next(),
+            Goal::Last => winner_iter.last(),
+        }
+        .map(|(number, boards)| (number, boards.iter().map(|x| x.index).collect()));
+        assert_eq!(Some((draws[draws.len() - 1], vec![board])), winner);
+
+        Ok(DrawOrder { draws, optimal })
+    }
+}
+
+/// Why a game has no single score.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BingoError {
+    NoWinner,
+    Tie { number: i32, boards: usize },
+    BoardTooLarge { board: usize, cells: usize },
+}
+
+impl fmt::Display for BingoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BingoError::NoWinner => write!(f, "no board wins"),
+            BingoError::Tie { number, boards } => {
+                write!(f, "{boards} boards win at once with {number}")
+            }
+            BingoError::BoardTooLarge { board, cells } => {
+                write!(f, "board {board} has {cells} cells, more than a mask holds")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BingoError {}
+
+/// Which score counts when several boards win with the same draw.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TiePolicy {
+    FirstByIndex,
+    HighestScore,
+    LowestScore,
+    Error,
+}
+
+impl TiePolicy {
+    /// Picks from the scores of the boards winning with `number`, ordered by
+    /// board index.
+    pub fn pick(self, number: i32, scores: &[i32]) -> Result<i32, BingoError> {
+        let picked = match self {
+            TiePolicy::FirstByIndex => scores.first(),
+            TiePolicy::HighestScore => scores.iter().max(),
+            TiePolicy::LowestScore => scores.iter().min(),
+            TiePolicy::Error if scores.len() > 1 => {
+                return Err(BingoError::Tie {
+                    number,
+                    boards: scores.len(),
+                })
+            }
+            TiePolicy::Error => scores.first(),
+        };
+        picked.copied().ok_or(BingoError::NoWinner)
+    }
+}
+
+fn winners_score<B: Board>(
+    winner: Option<(i32, Vec<B>)>,
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    let (number, boards) = winner.ok_or(BingoError::NoWinner)?;
+    let scores: Vec<_> = boards.iter().map(|x| x.sum_unchecked() * number).collect();
+    policy.pick(number, &scores)
+}
+
+#[aoc(day4, part1)]
+pub fn part1(input: &BingoInput) -> Result<i32, BingoError> {
+    part1_with(input, &STANDARD_PATTERNS, TiePolicy::Error)
+}
+
+pub fn part1_with(
+    input: &BingoInput,
+    patterns: &[WinPattern],
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    first_winner_score(input.get_winner_iter_with(patterns), policy)
+}
+
+fn first_winner_score<B: Board>(
+    mut winner_iter: BingoWinnerIter<B>,
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    winners_score(winner_iter.next(), policy)
+}
+
+#[aoc(day4, part2)]
+pub fn part2(input: &BingoInput) -> Result<i32, BingoError> {
+    part2_with(input, &STANDARD_PATTERNS, TiePolicy::Error)
+}
+
+pub fn part2_with(
+    input: &BingoInput,
+    patterns: &[WinPattern],
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    last_winner_score(input.get_winner_iter_with(patterns), policy)
+}
+
+fn last_winner_score<B: Board>(
+    winner_iter: BingoWinnerIter<B>,
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    winners_score(winner_iter.last(), policy)
+}
+
+#[aoc(day4, part1, mask)]
+pub fn part1_mask(input: &BingoInput) -> Result<i32, BingoError> {
+    first_winner_score(
+        input.get_mask_winner_iter_with(&STANDARD_PATTERNS)?,
+        TiePolicy::Error,
+    )
+}
+
+#[aoc(day4, part2, mask)]
+pub fn part2_mask(input: &BingoInput) -> Result<i32, BingoError> {
+    last_winner_score(
+        input.get_mask_winner_iter_with(&STANDARD_PATTERNS)?,
+        TiePolicy::Error,
+    )
+}
+
+/// Applies `policy` to the boards of `winners` winning on the same turn as
+/// `winner`.
+fn ranking_score(
+    winners: &[BoardWin],
+    winner: Option<&BoardWin>,
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    let winner = winner.ok_or(BingoError::NoWinner)?;
+    let scores: Vec<_> = winners
+        .iter()
+        .filter(|x| x.turn == winner.turn)
+        .map(|x| x.score)
+        .collect();
+    policy.pick(winner.number, &scores)
+}
+
+#[aoc(day4, part1, ranking)]
+pub fn part1_ranking(input: &BingoInput) -> Result<i32, BingoError> {
+    let winners = input.ranking().winners;
+    ranking_score(&winners, winners.first(), TiePolicy::Error)
+}
+
+#[aoc(day4, part2, ranking)]
+pub fn part2_ranking(input: &BingoInput) -> Result<i32, BingoError> {
+    let winners = input.ranking().winners;
+    ranking_score(&winners, winners.last(), TiePolicy::Error)
+}
+
+fn indexed_score(
+    engine: &IndexedBingo,
+    winner: Option<(i32, Vec<usize>)>,
+    policy: TiePolicy,
+) -> Result<i32, BingoError> {
+    let (number, mut boards) = winner.ok_or(BingoError::NoWinner)?;
+    boards.sort_unstable();
+    let scores: Vec<_> = boards
+        .iter()
+        .map(|x| engine.unchecked_sum(*x) * number)
+        .collect();
+    policy.pick(number, &scores)
+}
+
+#[aoc(day4, part1, indexed)]
+pub fn part1_indexed(input: &BingoInput) -> Result<i32, BingoError> {
+    let mut engine = IndexedBingo::new(input);
+    let winner = engine.next();
+    indexed_score(&engine, winner, TiePolicy::Error)
+}
+
+#[aoc(day4, part2, indexed)]
+pub fn part2_indexed(input: &BingoInput) -> Result<i32, BingoError> {
+    let mut engine = IndexedBingo::new(input);
+    let mut last = Err(BingoError::NoWinner);
+    while let Some(winner) = engine.next() {
+        last = indexed_score(&engine, Some(winner), TiePolicy::Error);
+    }
+
+    last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_INPUT: &str =
+        "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+
+    22 13 17 11  0
+     8  2 23  4 24
+    21  9 14 16  7
      6 10  3 18  5
      1 12 20 15 19
     
@@ -269,13 +1756,641 @@ mod tests {
         assert_eq!(expected, parse(TEST_INPUT));
     }
 
+    #[test]
+    fn test_parse_board_sizes() {
+        let input = parse_boards(
+            "1,2,3
+
+            1 2 3
+            4 5 6
+
+
+            7 8
+            9 10
+            11 12
+            ",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![(2, 3), (3, 2)],
+            input
+                .bingo_boards
+                .iter()
+                .map(|x| x.size())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            Err(BingoParseError::BoardSizeMismatch {
+                board: 1,
+                expected: (2, 3),
+                found: (3, 2)
+            }),
+            parse_boards("1\n\n1 2 3\n4 5 6\n\n7 8\n9 10\n11 12", true)
+        );
+        assert_eq!(
+            Err(BingoParseError::RaggedRow {
+                line: 4,
+                expected: 2,
+                found: 3
+            }),
+            parse_boards("1\n\n1 2\n3 4 5", false)
+        );
+        assert_eq!(
+            Err(BingoParseError::InvalidNumber {
+                line: 1,
+                value: "x".to_owned()
+            }),
+            parse_boards("1,x\n\n1 2\n3 4", false)
+        );
+        assert_eq!(Err(BingoParseError::MissingNumbers), parse_boards("", true));
+    }
+
+    #[test]
+    fn test_rectangular_board() {
+        // the column 2 5 of the 2x3 board is complete after drawing 2
+        let input = parse_boards("5,1,9,2\n\n1 2 3\n4 5 6\n\n7 8\n9 10\n11 12", false).unwrap();
+        assert_eq!(Ok((3 + 4 + 6) * 2), part1(&input));
+
+        let input = parse_boards("1,2,3,8\n\n1 2 3\n4 5 6\n\n7 8\n9 10\n11 12", false).unwrap();
+        assert_eq!(Ok((4 + 5 + 6) * 3), part1(&input));
+    }
+
+    #[test]
+    fn test_win_patterns() {
+        let mut board = BingoBoard::new(grid::grid![
+            [1, 2, 3]
+            [4, 5, 6]
+            [7, 8, 9]
+        ]);
+        let all = [
+            WinPattern::Row,
+            WinPattern::Column,
+            WinPattern::Diagonal,
+            WinPattern::FourCorners,
+            WinPattern::XShape,
+            WinPattern::FullCard,
+            WinPattern::Mask(vec![(0, 1), (1, 1), (1, 2)]),
+        ];
+
+        for num in [1, 5, 3, 7] {
+            board.check(num);
+        }
+        assert_eq!(BingoResult::NoBingo, board.bingo_with(&STANDARD_PATTERNS));
+        assert_eq!(
+            BingoResult::NoBingo,
+            board.bingo_with(&[WinPattern::XShape])
+        );
+        assert_eq!(
+            BingoResult::Diagonal(1),
+            board.bingo_with(&[WinPattern::XShape, WinPattern::Diagonal])
+        );
+
+        board.check(9);
+        assert_eq!(BingoResult::Diagonal(0), board.bingo_with(&all));
+        assert_eq!(BingoResult::FourCorners, board.bingo_with(&all[3..]));
+        assert_eq!(BingoResult::XShape, board.bingo_with(&all[4..]));
+        assert_eq!(BingoResult::NoBingo, board.bingo_with(&all[5..]));
+
+        board.check(2);
+        board.check(6);
+        assert_eq!(BingoResult::Mask(1), board.bingo_with(&all[5..]));
+        assert_eq!(
+            BingoResult::NoBingo,
+            board.bingo_with(&[WinPattern::Mask(vec![(0, 0), (3, 0)])])
+        );
+
+        for num in [4, 8] {
+            board.check(num);
+        }
+        assert_eq!(BingoResult::FullCard, board.bingo_with(&all[5..]));
+        assert_eq!(BingoResult::Row(0), board.bingo_with(&all));
+    }
+
+    #[test]
+    fn test_mask_board() {
+        let mut board = BingoBoard::new(grid::grid![
+            [1, 2, 3]
+            [4, 5, 6]
+        ]);
+        let all = [
+            WinPattern::Row,
+            WinPattern::Column,
+            WinPattern::Diagonal,
+            WinPattern::FourCorners,
+            WinPattern::XShape,
+            WinPattern::FullCard,
+            WinPattern::Mask(vec![(0, 1), (1, 2)]),
+            WinPattern::Mask(vec![(0, 0), (2, 0)]),
+        ];
+        let mut mask = MaskBoard::with_patterns(&board, &all).unwrap();
+
+        for num in [5, 3, 9, 1, 2, 6, 4] {
+            BingoBoard::check(&mut board, num);
+            Board::check(&mut mask, num);
+
+            assert_eq!(
+                BingoBoard::sum_unchecked(&board),
+                Board::sum_unchecked(&mask)
+            );
+            for i in 0..all.len() {
+                assert_eq!(
+                    BingoBoard::bingo_with(&board, &all[i..]),
+                    Board::bingo_with(&mask, &all[i..])
+                );
+            }
+            assert_eq!(
+                BingoBoard::bingo_with(&board, &all),
+                Board::bingo_with(&mask, &all)
+            );
+        }
+
+        let input = parse(TEST_INPUT);
+        for patterns in [&all[..2], &all[2..3], &all[3..6]] {
+            assert_eq!(
+                first_winner_score(
+                    input.get_winner_iter_with(patterns),
+                    TiePolicy::FirstByIndex
+                ),
+                first_winner_score(
+                    input.get_mask_winner_iter_with(patterns).unwrap(),
+                    TiePolicy::FirstByIndex
+                )
+            );
+            assert_eq!(
+                last_winner_score(
+                    input.get_winner_iter_with(patterns),
+                    TiePolicy::FirstByIndex
+                ),
+                last_winner_score(
+                    input.get_mask_winner_iter_with(patterns).unwrap(),
+                    TiePolicy::FirstByIndex
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_mask_board_too_large() {
+        let board = (0..9)
+            .map(|row| (0..9).map(|col| (row * 9 + col).to_string()).join(" "))
+            .join("\n");
+        let input = parse(&format!("0,1,2,3,4,5,6,7,8\n\n{board}"));
+
+        assert_eq!(Ok(8 * (80 * 81 / 2 - 36)), part1(&input));
+        let err = Err(BingoError::BoardTooLarge {
+            board: 0,
+            cells: 81,
+        });
+        assert_eq!(err, part1_mask(&input));
+        assert_eq!(err, part2_mask(&input));
+    }
+
+    #[test]
+    fn test_parts_with_patterns() {
+        let input = parse(TEST_INPUT);
+        assert_eq!(
+            Ok(4512),
+            part1_with(&input, &STANDARD_PATTERNS, TiePolicy::Error)
+        );
+        assert_eq!(
+            Ok(1924),
+            part2_with(&input, &STANDARD_PATTERNS, TiePolicy::Error)
+        );
+
+        // a full card leaves no unchecked numbers
+        assert_eq!(
+            Ok(0),
+            part1_with(&input, &[WinPattern::FullCard], TiePolicy::Error)
+        );
+        let input = parse_boards("5,1,9,2,3,7\n\n1 2 3\n4 5 6\n7 8 9\n\n1 2\n3 4", false).unwrap();
+        assert_eq!(
+            Ok(7 * (4 + 6 + 8)),
+            part1_with(&input, &[WinPattern::XShape], TiePolicy::Error)
+        );
+        // the diagonal 1 5 9 is complete before the corners and the small board
+        assert_eq!(
+            Ok(9 * (2 + 3 + 4 + 6 + 7 + 8)),
+            part1_with(
+                &input,
+                &[WinPattern::FourCorners, WinPattern::Diagonal],
+                TiePolicy::Error
+            )
+        );
+    }
+
+    #[test]
+    fn test_indexed() {
+        let input = parse(TEST_INPUT);
+        let indexed: Vec<_> = IndexedBingo::new(&input).collect();
+        let expected: Vec<_> = input
+            .get_winner_iter_with(&STANDARD_PATTERNS)
+            .map(|(num, boards)| (num, boards.len()))
+            .collect();
+        assert_eq!(
+            expected,
+            indexed
+                .iter()
+                .map(|(num, boards)| (*num, boards.len()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![(24, vec![2]), (16, vec![0]), (13, vec![1])], indexed);
+
+        // several winners on one draw and a rectangular board
+        let input = parse_boards("3,1,2,4\n\n1 2\n3 4\n\n4 3 1\n5 6 7\n\n3 9\n1 8", false).unwrap();
+        let mut engine = IndexedBingo::new(&input);
+        assert_eq!(Some((1, vec![0, 2])), engine.next());
+        assert_eq!(
+            (2 + 4, 9 + 8),
+            (engine.unchecked_sum(0), engine.unchecked_sum(2))
+        );
+        assert_eq!(Some((4, vec![1])), engine.next());
+        assert_eq!(5 + 6 + 7, engine.unchecked_sum(1));
+        assert_eq!(None, engine.next());
+
+        // a number drawn twice is only marked once
+        let input = parse_boards("1,1,5\n\n1 2\n3 4\n\n5 6\n7 8", false).unwrap();
+        assert_eq!(None, IndexedBingo::new(&input).next());
+        assert_eq!(
+            input.get_winner_iter_with(&STANDARD_PATTERNS).count(),
+            IndexedBingo::new(&input).count()
+        );
+        assert_eq!(Err(BingoError::NoWinner), part1_indexed(&input));
+        assert_eq!(part1(&input), part1_indexed(&input));
+    }
+
+    #[test]
+    fn test_ranking() {
+        let input = parse(TEST_INPUT);
+        assert_eq!(
+            BingoRanking {
+                winners: vec![
+                    BoardWin {
+                        board: 2,
+                        turn: 11,
+                        number: 24,
+                        score: 4512
+                    },
+                    BoardWin {
+                        board: 0,
+                        turn: 13,
+                        number: 16,
+                        score: 137 * 16
+                    },
+                    BoardWin {
+                        board: 1,
+                        turn: 14,
+                        number: 13,
+                        score: 1924
+                    },
+                ],
+                never_winning: vec![]
+            },
+            input.ranking()
+        );
+        assert_eq!(input.ranking().winners[1], input.board_win(0).unwrap());
+
+        let input = parse_boards("1,2,3\n\n1 2\n3 4\n\n5 6\n7 8\n\n3 9\n1 8", false).unwrap();
+        let ranking = input.ranking();
+        assert_eq!(vec![1], ranking.never_winning);
+        assert_eq!(
+            vec![(0, 1, 2 * (3 + 4)), (2, 2, 3 * (9 + 8))],
+            ranking
+                .winners
+                .iter()
+                .map(|x| (x.board, x.turn, x.score))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let input = parse(TEST_INPUT);
+        let turns = input.replay_with(&STANDARD_PATTERNS);
+
+        assert_eq!(15, turns.len());
+        assert_eq!(
+            vec![(11, 24, vec![2]), (13, 16, vec![0]), (14, 13, vec![1])],
+            turns
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| !x.winners.is_empty())
+                .map(|(i, x)| (i, x.number, x.winners.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![true, false, true], turns[13].won);
+        assert_eq!(188, turns[11].boards[2].sum_unchecked());
+
+        assert_eq!(
+            "[14][21][17][24][ 4]
+ 10  16  15 [ 9] 19
+ 18   8 [23] 26  20
+ 22 [11] 13   6 [ 5]
+[ 2][ 0] 12   3 [ 7]",
+            turns[11].boards[2].render(Highlight::Brackets)
+        );
+
+        let input = parse_boards("1\n\n1 2\n3 4", false).unwrap();
+        let turns = input.replay_with(&STANDARD_PATTERNS);
+        assert_eq!(
+            " \x1b[1;32m1\x1b[0m  2\n 3  4",
+            turns[0].boards[0].render(Highlight::Ansi)
+        );
+        assert_eq!(
+            "Drawn: 1\n\nBoard 1\n[1] 2\n 3  4\n",
+            turns[0].render(Highlight::Brackets)
+        );
+    }
+
+    #[test]
+    fn test_tie_policy() {
+        // both boards win with 3, the second one with the higher score
+        let input = parse_boards("1,3,2,4\n\n1 2\n3 4\n\n3 9\n1 8", false).unwrap();
+        type Part = fn(&BingoInput, TiePolicy) -> Result<i32, BingoError>;
+        let parts: [Part; 2] = [
+            |input, policy| part1_with(input, &STANDARD_PATTERNS, policy),
+            |input, policy| part2_with(input, &STANDARD_PATTERNS, policy),
+        ];
+        for part in parts {
+            assert_eq!(Ok(3 * (2 + 4)), part(&input, TiePolicy::FirstByIndex));
+            assert_eq!(Ok(3 * (9 + 8)), part(&input, TiePolicy::HighestScore));
+            assert_eq!(Ok(3 * (2 + 4)), part(&input, TiePolicy::LowestScore));
+            assert_eq!(
+                Err(BingoError::Tie {
+                    number: 3,
+                    boards: 2
+                }),
+                part(&input, TiePolicy::Error)
+            );
+        }
+        assert!(part1_indexed(&input).is_err());
+        assert!(part2_mask(&input).is_err());
+        assert!(part2_ranking(&input).is_err());
+
+        let input = parse_boards("1,4\n\n1 2\n3 4", false).unwrap();
+        assert_eq!(Err(BingoError::NoWinner), part1(&input));
+        assert_eq!(Err(BingoError::NoWinner), part2_indexed(&input));
+        assert_eq!(Err(BingoError::NoWinner), part1_ranking(&input));
+    }
+
+    #[test]
+    fn test_win_probabilities() {
+        let input = parse(TEST_INPUT);
+        let estimates = input.win_probabilities(2000, 7);
+        assert_eq!(estimates, input.win_probabilities(2000, 7));
+        assert_ne!(estimates, input.win_probabilities(2000, 8));
+
+        for estimate in estimates.first.iter().chain(&estimates.last) {
+            assert!(estimate.low <= estimate.probability);
+            assert!(estimate.probability <= estimate.high);
+            assert!(estimate.high - estimate.low < 0.05);
+        }
+        // every game has at least one first and one last winner
+        for estimates in [&estimates.first, &estimates.last] {
+            assert!(estimates.iter().map(|x| x.probability).sum::<f64>() >= 1.0);
+        }
+
+        // only two boards can win and they share no numbers
+        let input = parse_boards("1,2,3,4,5\n\n1 2\n3 4\n\n5\n\n9 8\n7 1", false).unwrap();
+        let estimates = input.win_probabilities(500, 1);
+        assert_eq!(
+            1.0,
+            estimates.first[0].probability + estimates.first[1].probability
+        );
+        assert_eq!(estimates.first[0], estimates.last[1]);
+        assert_eq!(0.0, estimates.first[2].probability);
+        assert_eq!(0.0, estimates.last[2].probability);
+        assert!(estimates.last[2].high < 0.01);
+    }
+
+    #[test]
+    fn test_draw_order_for() {
+        let input = parse(TEST_INPUT);
+        for board in 0..3 {
+            let order = input.draw_order_for(board, Goal::First, 0).unwrap();
+            assert_eq!(5, order.draws.len());
+            assert!(order.optimal);
+
+            let order = input.draw_order_for(board, Goal::Last, 100_000).unwrap();
+            assert!(order.draws.len() <= 15);
+            assert!(order.optimal);
+        }
+        assert_eq!(
+            Err(SearchError::NodeLimit),
+            input.draw_order_for(1, Goal::Last, 0)
+        );
+
+        // the last two boards share the line 5 6 which is enough for both to
+        // win before the first one
+        let input =
+            parse_boards("1,2,3,4,5,6,7,8\n\n1 2\n3 4\n\n5 6\n7 8\n\n2 5\n3 6", false).unwrap();
+        let order = input.draw_order_for(0, Goal::Last, 1000).unwrap();
+        assert_eq!(4, order.draws.len());
+        assert!(order.optimal);
+        assert_eq!(
+            vec![7, 8],
+            input.draw_order_for(1, Goal::First, 0).unwrap().draws
+        );
+
+        // every line of one board is a line of the other
+        let input = parse_boards("1,2,3,4\n\n1 2\n3 4\n\n1 3\n2 4", false).unwrap();
+        for goal in [Goal::First, Goal::Last] {
+            assert_eq!(
+                Err(SearchError::Impossible),
+                input.draw_order_for(0, goal, 1000)
+            );
+        }
+    }
+
+    const AMERICAN_CARD: &str = "B  I  N  G  O
+         4 19 33 50 61
+        15 24 40 47 75
+         1 30 FREE 59 62
+         9 16 45 46 70
+         7 22 31 55 66";
+
+    #[test]
+    fn test_parse_american() {
+        let mut card = BingoBoard::parse_american(AMERICAN_CARD).unwrap();
+        assert_eq!(Some(&BingoField::free()), card.board.get(2, 2));
+        assert_eq!(Some(&BingoField::new(59)), card.board.get(2, 3));
+        assert_eq!(
+            BingoBoard::parse_american(&AMERICAN_CARD.replace("B  I  N  G  O", "")),
+            Ok(card.clone())
+        );
+
+        // the free space counts for the middle row
+        for number in [1, 30, 59] {
+            card.check(number);
+            assert_eq!(BingoResult::NoBingo, card.bingo_with(&STANDARD_PATTERNS));
+        }
+        card.check(62);
+        assert_eq!(BingoResult::Row(2), card.bingo_with(&STANDARD_PATTERNS));
+
+        assert_eq!(
+            Err(BingoParseError::MissingFreeSpace { line: 4 }),
+            BingoBoard::parse_american(&AMERICAN_CARD.replace("FREE", "40"))
+        );
+        assert_eq!(
+            Err(BingoParseError::OutOfColumnRange {
+                line: 2,
+                column: 'G',
+                number: 45
+            }),
+            BingoBoard::parse_american(&AMERICAN_CARD.replace("50", "45"))
+        );
+        assert_eq!(
+            Err(BingoParseError::DuplicateNumber {
+                line: 6,
+                number: 55
+            }),
+            BingoBoard::parse_american(&AMERICAN_CARD.replace("46", "55"))
+        );
+        assert_eq!(
+            Err(BingoParseError::BoardSizeMismatch {
+                board: 0,
+                expected: (5, 5),
+                found: (4, 5)
+            }),
+            BingoBoard::parse_american(&AMERICAN_CARD.replace("7 22 31 55 66", ""))
+        );
+    }
+
+    #[test]
+    fn test_random_american() {
+        let mut rng = ChaCha8Rng::seed_from_u64(75);
+        for _ in 0..100 {
+            let card = BingoBoard::random_american(&mut rng);
+            let text = card.render(Highlight::Brackets);
+            assert_eq!(Ok(card), BingoBoard::parse_american(&text));
+        }
+    }
+
+    #[test]
+    fn test_bingo_game() {
+        let mut game = BingoGame::from_puzzle(&parse(TEST_INPUT), &STANDARD_PATTERNS);
+        let winners: Vec<_> = std::iter::from_fn(|| game.draw())
+            .filter(|(_, x)| !x.is_empty())
+            .collect();
+        assert_eq!(
+            vec![
+                (24, vec![(2, BingoResult::Row(0))]),
+                (16, vec![(0, BingoResult::Row(2))]),
+                (13, vec![(1, BingoResult::Column(2))]),
+            ],
+            winners
+        );
+        assert!(game.is_over());
+
+        let mut game = BingoGame::from_puzzle(&parse(TEST_INPUT), &STANDARD_PATTERNS);
+        let mut output = Vec::new();
+        game.play("\n".repeat(12).as_bytes(), &mut output, Highlight::Brackets)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Called 24\nBINGO! Player 3 wins with Row(0)\n"));
+        assert!(output.starts_with("Player 1\n 22  13  17  11   0\n"));
+        assert!(output.contains("Player 3\n[14][21][17][24][ 4]\n"));
+        assert!(!output.contains("Game over"));
+
+        let mut output = Vec::new();
+        game.play("q\n".as_bytes(), &mut output, Highlight::Brackets)
+            .unwrap();
+        assert_eq!(
+            1,
+            String::from_utf8(output)
+                .unwrap()
+                .matches("Player 1")
+                .count()
+        );
+        assert_eq!(15, game.draws.len());
+
+        // names for puzzle boards and the puzzle's numbers in random order
+        let mut input = parse(TEST_INPUT);
+        input.shuffle_numbers(&mut ChaCha8Rng::seed_from_u64(1));
+        assert_ne!(parse(TEST_INPUT).numbers, input.numbers);
+        assert_eq!(
+            parse(TEST_INPUT)
+                .numbers
+                .iter()
+                .sorted()
+                .collect::<Vec<_>>(),
+            input.numbers.iter().sorted().collect::<Vec<_>>()
+        );
+        let game = BingoGame::from_puzzle(&input, &STANDARD_PATTERNS)
+            .with_names(&["Ann".to_owned(), "Ben".to_owned()])
+            .unwrap();
+        assert_eq!(vec!["Ann", "Ben", "Player 3"], game.players);
+        assert_eq!(
+            Err("4 names for 3 players".to_owned()),
+            BingoGame::from_puzzle(&input, &STANDARD_PATTERNS)
+                .with_names(&vec!["Ann".to_owned(); 4])
+        );
+
+        // 75 balls complete every American card
+        let players = ["Ann".to_owned(), "Ben".to_owned()];
+        let patterns: Vec<WinPattern> = ["row", "column", "diagonal"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        let mut game = BingoGame::random(&players, &patterns, &mut ChaCha8Rng::seed_from_u64(4));
+        let mut output = Vec::new();
+        game.play("\n".repeat(75).as_bytes(), &mut output, Highlight::Ansi)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with("Game over\n"));
+        assert!(output.contains("BINGO! Ann wins with"));
+        assert!(output.contains("BINGO! Ben wins with"));
+        assert!(output.contains("Called N-"));
+        assert_eq!(
+            Err("unknown win pattern 'star'".to_owned()),
+            "star".parse::<WinPattern>()
+        );
+    }
+
+    #[test]
+    fn test_step_through() {
+        let turns = parse(TEST_INPUT).replay_with(&STANDARD_PATTERNS);
+        let mut output = Vec::new();
+        step_through(
+            &turns,
+            "\n\nq\n".as_bytes(),
+            &mut output,
+            Highlight::Brackets,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Turn 1/15\nDrawn: 7\n"));
+        assert!(output.contains("Turn 3/15\nDrawn: 9\n"));
+        assert!(!output.contains("Turn 4/15"));
+
+        let mut output = Vec::new();
+        step_through(
+            &turns,
+            "\n".repeat(20).as_bytes(),
+            &mut output,
+            Highlight::Brackets,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Turn 15/15\nDrawn: 13\n"));
+        assert!(output.contains("Board 2 - BINGO!"));
+        assert!(output.ends_with("End of replay\n"));
+    }
+
     #[test]
     fn test_part1() {
-        assert_eq!(4512, part1(&parse(TEST_INPUT)));
+        assert_eq!(Ok(4512), part1(&parse(TEST_INPUT)));
+        assert_eq!(Ok(4512), part1_indexed(&parse(TEST_INPUT)));
+        assert_eq!(Ok(4512), part1_mask(&parse(TEST_INPUT)));
+        assert_eq!(Ok(4512), part1_ranking(&parse(TEST_INPUT)));
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(1924, part2(&parse(TEST_INPUT)));
+        assert_eq!(Ok(1924), part2(&parse(TEST_INPUT)));
+        assert_eq!(Ok(1924), part2_indexed(&parse(TEST_INPUT)));
+        assert_eq!(Ok(1924), part2_mask(&parse(TEST_INPUT)));
+        assert_eq!(Ok(1924), part2_ranking(&parse(TEST_INPUT)));
     }
 }
@@ -5,7 +5,7 @@ pub struct Crab {
 
 impl Crab {
     fn fuel_2(&self, pos: i32) -> usize {
-        (0..=((self.position - pos).abs() as usize)).sum()
+        (0..=(self.position.abs_diff(pos) as usize)).sum()
     }
 }
 
@@ -28,7 +28,7 @@ pub fn part1(input: &[Crab]) -> usize {
         .map(|pos| {
             input
                 .iter()
-                .map(|x| (x.position - pos).abs() as usize)
+                .map(|x| x.position.abs_diff(pos) as usize)
                 .sum()
         })
         .min()
@@ -64,16 +64,12 @@ impl From<&str> for SignalNote {
                 .trim()
                 .split(' ')
                 .map(|x| Pattern(x.to_owned()))
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
+                .collect(),
             output: output
                 .trim()
                 .split(' ')
                 .map(|x| Pattern(x.to_owned()))
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
+                .collect(),
         }
     }
 }
@@ -172,15 +168,14 @@ impl SignalNote {
 
 #[aoc_generator(day8)]
 pub fn parse(input: &str) -> Vec<SignalNote> {
-    input.lines().map(|x| SignalNote::from(x)).collect()
+    input.lines().map(SignalNote::from).collect()
 }
 
 #[aoc(day8, part1)]
 pub fn part1(input: &[SignalNote]) -> usize {
     input
         .iter()
-        .map(|x| &x.output)
-        .flatten()
+        .flat_map(|x| &x.output)
         .filter(|x| {
             x.0.len() == ONE.len()
                 || x.0.len() == FOUR.len()